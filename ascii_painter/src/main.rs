@@ -1,11 +1,17 @@
 //! This crate is the main entry of ascii_painter. It processes command parameters and generates
 //! callgraph diagram using [`Painter`] and [`Canvas`].
 use canvas::Canvas;
+use painter::callgraph::{Numbering, ParseError, Style};
+use painter::config::{Diagram, PainterConfig};
 use painter::Painter;
 use std::io::{self, Read};
-use std::{fs::File, io::Write, path::PathBuf};
+use std::{env, fs, fs::File, io::Write, path::PathBuf, process};
 use structopt::StructOpt;
 
+/// Name of the config file looked up in the current directory and its
+/// ancestors when `--config` is not given.
+const CONFIG_FILE_NAME: &str = ".ascii_painter.toml";
+
 /// Returns true if and only if stdin is believed to be connectted to a tty
 /// or a console.
 pub fn is_tty_stdin() -> bool {
@@ -26,6 +32,182 @@ struct Opt {
     /// Output file where the result graph is written to.
     #[structopt(short, long, parse(from_os_str))]
     output: Option<PathBuf>,
+
+    /// Config file of the drawing settings, `.ascii_painter.toml` in the
+    /// current directory or its ancestors by default.
+    #[structopt(short, long, parse(from_os_str))]
+    config: Option<PathBuf>,
+
+    /// Kind of diagram drawn, either a UML `sequence` diagram, a `tree` of
+    /// the calls grouped by component, a `collaboration` diagram of the
+    /// components calling each other, a `class` diagram of the components
+    /// with their functions, or a `flowchart` of indented steps with `if`
+    /// and `else` branches.
+    #[structopt(
+        long,
+        possible_values =
+            &["sequence", "tree", "collaboration", "class", "flowchart"]
+    )]
+    diagram: Option<Diagram>,
+
+    /// Follows each function of a tree diagram with its number of calls.
+    #[structopt(long)]
+    call_counts: bool,
+
+    /// Packs the boxes of a class diagram into a grid at most this many
+    /// columns wide.
+    #[structopt(long)]
+    class_grid_width: Option<usize>,
+
+    /// Prefixes each message with its sequence number, either `flat` (1, 2,
+    /// 3) or `hierarchical` by call nesting (1, 1.1, 2).
+    #[structopt(long, possible_values = &["flat", "hierarchical"])]
+    autonumber: Option<Numbering>,
+
+    /// Wraps message labels longer than this width to multiple lines.
+    #[structopt(long)]
+    max_label_width: Option<usize>,
+
+    /// Repeats each participant box at the bottom of its lifeline.
+    #[structopt(long)]
+    footers: bool,
+
+    /// Repeats the participant boxes after every N messages.
+    #[structopt(long, value_name = "N")]
+    repeat_header_every: Option<usize>,
+
+    /// Columns between tab stops in the indentation of the input text.
+    #[structopt(long)]
+    tab_width: Option<usize>,
+
+    /// Separator between the segments of a qualified path, e.g. `.` for
+    /// `com.example.Service.handle`, or `->` even though it starts with `-`.
+    /// Can be given multiple times.
+    #[structopt(
+        long = "separator",
+        number_of_values = 1,
+        allow_hyphen_values = true
+    )]
+    separators: Vec<String>,
+
+    /// Shortens participant labels to their last N path segments.
+    #[structopt(long, value_name = "N")]
+    component_segments: Option<usize>,
+
+    /// Leaves the `(args)` of calls out of message labels.
+    #[structopt(long)]
+    hide_args: bool,
+
+    /// Leaves the `[timing]` of calls out of message labels.
+    #[structopt(long)]
+    hide_timing: bool,
+
+    /// Draws the `# annotation` of calls in message labels.
+    #[structopt(long)]
+    show_annotations: bool,
+
+    /// Characters the diagram is drawn with, either `unicode` box-drawing
+    /// characters or plain `ascii`.
+    #[structopt(long, possible_values = &["unicode", "ascii"])]
+    style: Option<Style>,
+
+    /// Title drawn above the diagram.
+    #[structopt(long)]
+    title: Option<String>,
+
+    /// Caption drawn below the diagram.
+    #[structopt(long)]
+    caption: Option<String>,
+
+    /// Footer drawn below the caption.
+    #[structopt(long)]
+    footer: Option<String>,
+
+    /// Draws a legend of the arrow styles below the diagram.
+    #[structopt(long)]
+    legend: bool,
+
+    /// Splits the diagram into pages of participants, each at most this many
+    /// columns wide.
+    #[structopt(long)]
+    max_width: Option<usize>,
+
+    /// Splits the diagram into pages of messages, each at most this many rows
+    /// high, separated by form feeds.
+    #[structopt(long)]
+    max_height: Option<usize>,
+
+    /// Folds call subtrees repeated at least this many times in a row into a
+    /// `loop` frame.
+    #[structopt(long)]
+    fold_loops: Option<usize>,
+
+    /// Leaves out the calls nested deeper than this many calls.
+    #[structopt(long)]
+    max_depth: Option<usize>,
+
+    /// Draws only the participants matching this name or glob, e.g.
+    /// `Order*`. Can be given multiple times.
+    #[structopt(long, number_of_values = 1)]
+    include: Vec<String>,
+
+    /// Leaves out the participants matching this name or glob. Can be given
+    /// multiple times.
+    #[structopt(long, number_of_values = 1)]
+    exclude: Vec<String>,
+
+    /// Leaves out the calls of a participant to itself.
+    #[structopt(long)]
+    hide_self_calls: bool,
+
+    /// Collapses the participants left out into a single `…` participant.
+    #[structopt(long)]
+    collapse_excluded: bool,
+}
+
+/// Loads [`PainterConfig`] from `path`, or from the nearest
+/// `.ascii_painter.toml` if `path` is `None`.
+fn load_config(path: Option<PathBuf>) -> io::Result<PainterConfig> {
+    let path = match path {
+        Some(path) => path,
+        None => {
+            let dir = env::current_dir()?;
+            let found = dir
+                .ancestors()
+                .map(|dir| dir.join(CONFIG_FILE_NAME))
+                .find(|path| path.is_file());
+            match found {
+                Some(path) => path,
+                None => return Ok(PainterConfig::default()),
+            }
+        }
+    };
+    let text = fs::read_to_string(&path)?;
+    PainterConfig::from_toml(&text).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", path.display(), e),
+        )
+    })
+}
+
+/// Prints `errors` of the callgraph text read from `source` to stderr, each
+/// with the offending line and a caret under the unexpected part.
+fn print_errors(source: &str, errors: &[ParseError]) {
+    for error in errors {
+        let number = error.line.to_string();
+        let gutter = " ".repeat(number.len());
+        eprintln!("error: {}", error.message);
+        eprintln!("{}--> {}:{}:{}", gutter, source, error.line, error.column);
+        eprintln!("{} |", gutter);
+        eprintln!("{} | {}", number, error.snippet);
+        eprintln!(
+            "{} | {}{}",
+            gutter,
+            " ".repeat(error.column - 1),
+            "^".repeat(error.len.max(1))
+        );
+    }
 }
 
 /// Main entry of ascii_painter program.
@@ -34,7 +216,10 @@ fn main() -> io::Result<()> {
 
     let mut buffer = String::new();
 
-    if opt.input.is_none() {
+    if let Some(input) = &opt.input {
+        let mut f = File::open(input.as_path())?;
+        f.read_to_string(&mut buffer)?;
+    } else {
         if is_tty_stdin() {
             println!("Need input text from either piped stdin or file. Use --help (-h) for help message.");
             return Ok(());
@@ -42,9 +227,6 @@ fn main() -> io::Result<()> {
         let stdin = io::stdin();
         let mut handle = stdin.lock();
         handle.read_to_string(&mut buffer)?;
-    } else {
-        let mut f = File::open(opt.input.unwrap().as_path())?;
-        f.read_to_string(&mut buffer)?;
     }
 
     if buffer.is_empty() {
@@ -54,17 +236,118 @@ fn main() -> io::Result<()> {
 
     let mut canvas = Canvas::new(10000, 10000);
 
-    let mut painter = Painter::new();
+    let mut config = load_config(opt.config)?;
+    if let Some(diagram) = opt.diagram {
+        config.diagram = diagram;
+    }
+    if opt.call_counts {
+        config.call_counts = true;
+    }
+    if opt.class_grid_width.is_some() {
+        config.class_grid_width = opt.class_grid_width;
+    }
+    if opt.autonumber.is_some() {
+        config.autonumber = opt.autonumber;
+    }
+    if let Some(width) = opt.max_label_width {
+        config.max_label_width = width;
+    }
+    if opt.footers {
+        config.footers = true;
+    }
+    if opt.repeat_header_every.is_some() {
+        config.repeat_header_every = opt.repeat_header_every;
+    }
+    if let Some(width) = opt.tab_width {
+        config.tab_width = width;
+    }
+    if !opt.separators.is_empty() {
+        config.separators = opt.separators;
+    }
+    if opt.component_segments.is_some() {
+        config.component_segments = opt.component_segments;
+    }
+    if opt.hide_args {
+        config.show_args = false;
+    }
+    if opt.hide_timing {
+        config.show_timing = false;
+    }
+    if opt.show_annotations {
+        config.show_annotations = true;
+    }
+    if opt.style.is_some() {
+        config.style = opt.style;
+    }
+    if opt.title.is_some() {
+        config.title = opt.title;
+    }
+    if opt.caption.is_some() {
+        config.caption = opt.caption;
+    }
+    if opt.footer.is_some() {
+        config.footer = opt.footer;
+    }
+    if opt.legend {
+        config.legend = true;
+    }
+    if opt.max_width.is_some() {
+        config.max_width = opt.max_width;
+    }
+    if opt.max_height.is_some() {
+        config.max_height = opt.max_height;
+    }
+    if opt.fold_loops.is_some() {
+        config.fold_loops = opt.fold_loops;
+    }
+    if opt.max_depth.is_some() {
+        config.max_depth = opt.max_depth;
+    }
+    if !opt.include.is_empty() {
+        config.include = opt.include;
+    }
+    if !opt.exclude.is_empty() {
+        config.exclude = opt.exclude;
+    }
+    if opt.hide_self_calls {
+        config.hide_self_calls = true;
+    }
+    if opt.collapse_excluded {
+        config.collapse_excluded = true;
+    }
 
-    painter.draw(&mut canvas, &buffer);
+    let paged = config.max_height.is_some();
+    let mut painter = Painter::new(config);
 
-    canvas.reset_boundary();
+    let drawn = if paged {
+        painter.draw_pages(&buffer).map(|pages| {
+            let pages: Vec<String> =
+                pages.iter().map(|page| page.to_string()).collect();
+            pages.join("\u{c}\n")
+        })
+    } else {
+        painter.draw(&mut canvas, &buffer).map(|()| {
+            canvas.reset_boundary();
+            canvas.to_string()
+        })
+    };
+    let text = match drawn {
+        Ok(text) => text,
+        Err(errors) => {
+            let source = match &opt.input {
+                Some(input) => input.display().to_string(),
+                None => String::from("<stdin>"),
+            };
+            print_errors(&source, &errors);
+            process::exit(1);
+        }
+    };
 
-    if opt.output.is_some() {
-        let mut output_f = File::create(opt.output.unwrap().as_path())?;
-        output_f.write(canvas.to_string().as_bytes())?;
+    if let Some(output) = &opt.output {
+        let mut output_f = File::create(output.as_path())?;
+        output_f.write_all(text.as_bytes())?;
     } else {
-        canvas.print();
+        print!("{}", text);
     }
 
     Ok(())
@@ -2,12 +2,12 @@
 //! [`Line`], with label or not.
 
 use std::cmp::{max, min};
-use std::{mem, str};
+use std::{fmt, mem, str};
 
 type Vertex = (usize, usize);
 
 /// Defines a rectangle with four boundaries.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Rectangle {
     pub left: usize,
     pub right: usize,
@@ -31,16 +31,15 @@ pub struct Canvas {
     boundary: Rectangle,
 }
 
-impl ToString for Canvas {
-    fn to_string(&self) -> String {
-        let mut ret = String::new();
+impl fmt::Display for Canvas {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         for i in self.boundary.top..=self.boundary.bottom {
             for j in self.boundary.left..=self.boundary.right {
-                ret.push(self.buffer[i][j]);
+                write!(f, "{}", self.buffer[i][j])?;
             }
-            ret.push('\n');
+            writeln!(f)?;
         }
-        ret
+        Ok(())
     }
 }
 
@@ -72,6 +71,13 @@ impl Canvas {
         self.change_pixel(vertex, c);
     }
 
+    /// Writes `text` on one line starting from `vertex`.
+    pub fn draw_text(&mut self, vertex: &Vertex, text: &str) {
+        for (j, c) in text.chars().enumerate() {
+            self.change_pixel(&(vertex.0, vertex.1 + j), c);
+        }
+    }
+
     fn draw_line_overwrite_or_not(
         &mut self,
         a: &Vertex,
@@ -105,20 +111,41 @@ impl Canvas {
         self.draw_line_overwrite_or_not(a, b, false)
     }
 
+    /// Draws a line from `start` to `end` with a filled arrowhead at `end`.
     pub fn draw_arrowed_line(&mut self, start: &Vertex, end: &Vertex) -> Line {
+        self.draw_arrowed_line_with_heads(start, end, ['▼', '▲', '►', '◄'])
+    }
+
+    /// Draws a line from `start` to `end` with an open arrowhead at `end`.
+    pub fn draw_open_arrowed_line(
+        &mut self,
+        start: &Vertex,
+        end: &Vertex,
+    ) -> Line {
+        self.draw_arrowed_line_with_heads(start, end, ['▽', '△', '▷', '◁'])
+    }
+
+    /// Draws an arrowed line using `heads` for the down, up, right and left
+    /// directions.
+    fn draw_arrowed_line_with_heads(
+        &mut self,
+        start: &Vertex,
+        end: &Vertex,
+        heads: [char; 4],
+    ) -> Line {
         let line = self.draw_line(start, end);
         let mut c = '?';
         if start.0 != end.0 {
             if start.0 < end.0 {
-                c = '▼';
+                c = heads[0];
             } else {
-                c = '▲';
+                c = heads[1];
             }
         } else if start.1 != end.1 {
             if start.1 < end.1 {
-                c = '►';
+                c = heads[2];
             } else {
-                c = '◄';
+                c = heads[3];
             }
         }
         self.change_pixel(end, c);
@@ -136,9 +163,9 @@ impl Canvas {
                     if new_line && c == ' ' {
                     } else {
                         self.change_pixel(&(i, j), c);
-                        j = j + 1;
+                        j += 1;
                     }
-                    k = k + 1;
+                    k += 1;
                     new_line = false;
                 } else {
                     return;
@@ -238,6 +265,17 @@ impl Canvas {
         }
     }
 
+    /// Sets the boundary to the whole canvas, so that pages of the same size
+    /// print with their surrounding empty space.
+    pub fn fill_boundary(&mut self) {
+        self.boundary = Rectangle {
+            left: 0,
+            right: self.width - 1,
+            top: 0,
+            bottom: self.height - 1,
+        };
+    }
+
     /// Resets boundary to crop the surrounding empty space.
     pub fn reset_boundary(&mut self) {
         for i in 0..self.height {
@@ -276,6 +314,37 @@ impl Canvas {
         }
     }
 
+    /// Replaces box-drawing characters and arrowheads with their closest
+    /// plain ASCII characters.
+    pub fn asciify(&mut self) {
+        for c in self.buffer.iter_mut().flatten() {
+            *c = match *c {
+                '─' => '-',
+                '═' => '=',
+                '│' => '|',
+                '┊' | '⋮' => ':',
+                '×' => 'x',
+                '┌' | '┐' | '└' | '┘' | '├' | '┤' | '┬' | '┴' | '┼' => {
+                    '+'
+                }
+                '╭' | '╮' => '.',
+                '╰' | '╯' => '\'',
+                '◇' => '*',
+                '□' => '#',
+                '▼' => 'v',
+                '▲' => '^',
+                '►' => '>',
+                '◄' => '<',
+                // open heads stay apart from the filled ones, as in `-)`
+                '▽' => 'V',
+                '△' => 'A',
+                '▷' => ')',
+                '◁' => '(',
+                c => c,
+            };
+        }
+    }
+
     pub fn clear(&mut self) {
         self.buffer = vec![vec![' '; self.width]; self.height];
         self.boundary = Rectangle {
@@ -348,6 +417,28 @@ mod test {
         assert_eq!(canvas.to_string(), res);
     }
 
+    #[test]
+    fn test_asciify() {
+        let mut canvas = Canvas::new(20, 20);
+        canvas.draw_rectangle(&Rectangle {
+            left: 1,
+            right: 4,
+            top: 1,
+            bottom: 3,
+        });
+        canvas.draw_arrowed_line(&(2, 4), &(2, 8));
+        canvas.asciify();
+        canvas.reset_boundary();
+        assert_eq!(canvas.to_string(), "+--+    \n|  |--->\n+--+    \n");
+
+        let mut canvas = Canvas::new(20, 20);
+        canvas.draw_arrowed_line(&(0, 0), &(0, 4));
+        canvas.draw_open_arrowed_line(&(1, 0), &(1, 4));
+        canvas.asciify();
+        canvas.reset_boundary();
+        assert_eq!(canvas.to_string(), "--->\n---)\n");
+    }
+
     #[test]
     fn test_line_with_long_label() {
         let mut canvas = Canvas::new(20, 20);
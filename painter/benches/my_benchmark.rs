@@ -0,0 +1,20 @@
+use canvas::Canvas;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use painter::config::PainterConfig;
+use painter::Painter;
+use std::fs;
+
+fn draw(c: &mut Criterion) {
+    let txt = fs::read_to_string("./test/callgraph_markers.txt").unwrap();
+    let mut painter = Painter::new(PainterConfig::default());
+
+    c.bench_function("draw callgraph_markers", |b| {
+        b.iter(|| {
+            let mut canvas = Canvas::new(500, 500);
+            painter.draw(&mut canvas, black_box(&txt)).unwrap();
+        })
+    });
+}
+
+criterion_group!(benches, draw);
+criterion_main!(benches);
@@ -1,11 +1,269 @@
 //! This module includes the [`CallGraph`] type, which contains the structured information of the
 //! callgraph.
+use serde::Deserialize;
 use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
 
 /// Component and its function name
-type Function = (String, String);
-/// Component and the function it's calling
-pub type FunctionCall = (String, Function);
+pub type Function = (String, String);
+
+/// A function call statement of the callgraph.
+#[derive(Debug, Clone)]
+pub struct FunctionCall {
+    /// Identifier of the calling component, empty if called from outside.
+    pub caller: String,
+    /// The called component and its function.
+    pub callee: Function,
+    /// Sequence number by call nesting, e.g. `[2, 3]` for step 2.3.
+    pub number: Vec<usize>,
+    /// How the call is drawn.
+    pub kind: MessageKind,
+    /// Argument list within `(...)` after the function, if any.
+    pub args: Option<String>,
+    /// Timing within `[...]`, e.g. `12ms`.
+    pub timing: Option<String>,
+    /// Free text after `#` or `//` at the end of the statement.
+    pub annotation: Option<String>,
+}
+
+/// Arguments, timing and annotation following the path of a statement.
+#[derive(Debug, Default)]
+struct Metadata {
+    args: Option<String>,
+    timing: Option<String>,
+    annotation: Option<String>,
+}
+
+/// Type of the message drawn for a function call, marked by an optional
+/// keyword in front of the statement, e.g. `async ClassB::notify`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MessageKind {
+    /// A synchronous call, the default.
+    Sync,
+    /// An asynchronous call, drawn with an open arrowhead.
+    Async,
+    /// A call creating the called component, whose box is drawn at the row
+    /// of the call instead of the top.
+    Create,
+    /// A call destroying the called component, whose lifeline ends at the
+    /// row of the call.
+    Destroy,
+}
+
+impl MessageKind {
+    /// Splits the message kind keyword from the front of `statement`.
+    fn split(statement: &str) -> (Self, &str) {
+        let keywords = [
+            ("async", MessageKind::Async),
+            ("create", MessageKind::Create),
+            ("destroy", MessageKind::Destroy),
+        ];
+        for (keyword, kind) in keywords.iter() {
+            if let Some(rest) = strip_keyword(statement, keyword) {
+                if !rest.is_empty() {
+                    return (*kind, rest);
+                }
+            }
+        }
+        (MessageKind::Sync, statement)
+    }
+}
+
+/// Returns the width of `indent` whitespace, with each tab advancing to the
+/// next multiple of `tab_width`.
+pub(crate) fn indent_width(indent: &str, tab_width: usize) -> usize {
+    indent.chars().fold(0, |width, c| match c {
+        '\t' if tab_width > 0 => (width / tab_width + 1) * tab_width,
+        '\t' => width,
+        _ => width + 1,
+    })
+}
+
+/// Returns the name of a `== name ==` divider `line`.
+fn divider_name(line: &str) -> Option<&str> {
+    let name = line.strip_prefix("==")?.strip_suffix("==")?;
+    Some(name.trim_matches(|c| c == '=' || c == ' '))
+}
+
+/// Returns the rest of `line` if it starts with the word `keyword`.
+pub(crate) fn strip_keyword<'a>(
+    line: &'a str,
+    keyword: &str,
+) -> Option<&'a str> {
+    let rest = line.strip_prefix(keyword)?;
+    if rest.is_empty() || rest.starts_with(char::is_whitespace) {
+        Some(rest.trim_start())
+    } else {
+        None
+    }
+}
+
+/// Settings of parsing callgraph text.
+#[derive(Debug, Clone)]
+pub struct ParseOptions {
+    /// Columns between tab stops in the indentation.
+    pub tab_width: usize,
+    /// Separators between the segments of a qualified path, e.g. `::` in
+    /// `std::collections::HashMap::insert`.
+    pub separators: Vec<String>,
+    /// Number of trailing path segments the component label is shortened
+    /// to, e.g. `HashMap` of `std::collections::HashMap` with 1.
+    pub component_segments: Option<usize>,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            tab_width: 4,
+            separators: vec![String::from("::")],
+            component_segments: None,
+        }
+    }
+}
+
+/// An error in the callgraph text that cannot be understood.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    /// 1-based line number.
+    pub line: usize,
+    /// 1-based column, in chars, where the error starts.
+    pub column: usize,
+    /// Number of chars the error spans.
+    pub len: usize,
+    pub message: String,
+    /// The line of callgraph text containing the error.
+    pub snippet: String,
+}
+
+impl ParseError {
+    /// Creates the error spanning `len` bytes from byte `offset` of the
+    /// `index`-th `line`.
+    pub(crate) fn new(
+        index: usize,
+        line: &str,
+        offset: usize,
+        len: usize,
+        message: String,
+    ) -> Self {
+        ParseError {
+            line: index + 1,
+            column: line[..offset].chars().count() + 1,
+            len: line[offset..offset + len].chars().count(),
+            message,
+            snippet: line.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Byte offset and length within a statement, and the message of an error.
+type StatementError = (usize, usize, String);
+
+/// Numbering scheme of the messages.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Numbering {
+    /// Messages are numbered 1, 2, 3, ... by their occurence.
+    Flat,
+    /// Messages are numbered 1, 1.1, 1.1.1, 2, ... by their call nesting.
+    Hierarchical,
+}
+
+impl FromStr for Numbering {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "flat" => Ok(Numbering::Flat),
+            "hierarchical" => Ok(Numbering::Hierarchical),
+            _ => Err(format!("unknown numbering scheme `{}`", s)),
+        }
+    }
+}
+
+/// Characters the diagram is drawn with.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Style {
+    /// Box-drawing characters and filled arrowheads, the default.
+    Unicode,
+    /// Plain ASCII characters only, e.g. `+`, `-`, `|` and `>`.
+    Ascii,
+}
+
+impl FromStr for Style {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "unicode" => Ok(Style::Unicode),
+            "ascii" => Ok(Style::Ascii),
+            _ => Err(format!("unknown style `{}`", s)),
+        }
+    }
+}
+
+/// A mark between two function calls of the diagram.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Marker {
+    /// A named divider between sections, from `== name ==`.
+    Divider(String),
+    /// Elapsed time, from `...` optionally followed by a description.
+    Delay(Option<String>),
+}
+
+/// Participants drawn together in a labeled box, e.g. the ones living in
+/// the same process, from `box "Backend" ClassB ClassC end`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Group {
+    pub label: String,
+    /// Identifiers of the grouped components.
+    pub components: Vec<String>,
+}
+
+/// A run of calls repeated in a row, folded into its first instance.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Loop {
+    /// Range in `func_calls` of the calls of the instance kept.
+    pub calls: Range<usize>,
+    /// How many times the calls were repeated.
+    pub count: usize,
+}
+
+/// Diagram-level settings given by `@`-directives in the callgraph text.
+#[derive(Debug, Default)]
+pub struct Header {
+    /// Title of the diagram, from `@title <text>`.
+    pub title: Option<String>,
+    /// Caption below the diagram, from `@caption <text>`.
+    pub caption: Option<String>,
+    /// Footer below the caption, from `@footer <text>`.
+    pub footer: Option<String>,
+    /// Message numbering, from `@autonumber [flat|hierarchical]` or its
+    /// `autonumber` shorthand.
+    pub autonumber: Option<Numbering>,
+    /// Characters the diagram is drawn with, from `@style ascii|unicode`.
+    pub style: Option<Style>,
+}
+
+/// Explicit declaration of a participant in the callgraph header, e.g.
+/// `participant PaymentGatewayClientImpl as PGW "Payment Gateway"`.
+#[derive(Debug, Default)]
+pub struct Participant {
+    /// Short name that can be used in place of the identifier in statements.
+    pub alias: Option<String>,
+    /// Label drawn in the participant box instead of the identifier.
+    pub display_name: Option<String>,
+}
 
 /// A structure of callgraph information.
 #[derive(Debug)]
@@ -16,39 +274,185 @@ pub struct CallGraph {
     pub components_in_order: Vec<String>,
     /// A vec of function calls ([`FunctionCall`]) by their occurence.
     pub func_calls: Vec<FunctionCall>,
+    /// A map between component identifier to its [`Participant`] declaration.
+    pub participants: HashMap<String, Participant>,
+    /// A map between participant alias to its component identifier.
+    aliases: HashMap<String, String>,
+    /// Identifiers of the declared participants, in the order of their
+    /// declarations.
+    declared: Vec<String>,
+    /// Diagram-level settings of the `@`-directives.
+    pub header: Header,
+    /// Dividers and delays, each with the index in `func_calls` of the call
+    /// it precedes.
+    pub markers: Vec<(usize, Marker)>,
+    /// Groups of participants drawn in a box, whose components are next to
+    /// each other in `components_in_order`.
+    pub groups: Vec<Group>,
+    /// Repeated calls folded by [`CallGraph::fold_loops`], the outer ones
+    /// first.
+    pub loops: Vec<Loop>,
+    /// Options the callgraph text was parsed with.
+    options: ParseOptions,
 }
 
 impl CallGraph {
-    /// Processes `callgraph` text and generates [`CallGraph`]
-    pub fn new(callgraph: &str) -> Self {
+    /// Processes `callgraph` text and generates [`CallGraph`], or the
+    /// [`ParseError`]s of all the lines that cannot be understood.
+    pub fn new(callgraph: &str) -> Result<Self, Vec<ParseError>> {
+        Self::with_options(callgraph, &ParseOptions::default())
+    }
+
+    /// Same as [`CallGraph::new`], parsing `callgraph` text with `options`.
+    pub fn with_options(
+        callgraph: &str,
+        options: &ParseOptions,
+    ) -> Result<Self, Vec<ParseError>> {
         let mut ret = CallGraph {
             components: HashMap::new(),
             components_in_order: Vec::new(),
             func_calls: Vec::new(),
+            participants: HashMap::new(),
+            aliases: HashMap::new(),
+            declared: Vec::new(),
+            header: Header::default(),
+            markers: Vec::new(),
+            groups: Vec::new(),
+            loops: Vec::new(),
+            options: options.clone(),
         };
 
+        let mut errors = Vec::new();
         let mut function_stack: Vec<(String, usize)> = Vec::new();
+        // open indentation levels, the innermost last
+        let mut indents: Vec<usize> = vec![0];
+        let mut numbers: Vec<usize> = Vec::new();
         let mut last_component = String::new();
         let mut last_depth = 0;
-        for line in callgraph.split('\n') {
-            let parts: Vec<&str> = line.split("::").collect();
-            if parts.len() < 1 {
+        // components in a call so far, and the ones destroyed since
+        let mut used: HashSet<String> = HashSet::new();
+        let mut destroyed: HashSet<String> = HashSet::new();
+        for (index, line) in callgraph.split('\n').enumerate() {
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            let trimmed = line.trim();
+            let non_space_pos = line.len() - line.trim_start().len();
+            // blank lines separate sections, closing all the levels
+            let depth = if trimmed.is_empty() {
+                0
+            } else {
+                indent_width(&line[..non_space_pos], options.tab_width)
+            };
+            let error = |(offset, len, message): StatementError| {
+                ParseError::new(
+                    index,
+                    line,
+                    non_space_pos + offset,
+                    len,
+                    message,
+                )
+            };
+
+            if trimmed.starts_with('#') || trimmed.starts_with("//") {
+                continue;
+            }
+            if let Some(delay) = trimmed.strip_prefix("...") {
+                let delay = delay.trim_matches(|c| c == '.' || c == ' ');
+                let delay = Some(delay.to_string()).filter(|d| !d.is_empty());
+                let index = ret.func_calls.len();
+                ret.markers.push((index, Marker::Delay(delay)));
+                continue;
+            }
+            if let Some(name) = divider_name(trimmed) {
+                let index = ret.func_calls.len();
+                ret.markers.push((index, Marker::Divider(name.to_string())));
+                // a divider ends the section like a blank line
+                function_stack.clear();
+                indents.truncate(1);
+                last_component.clear();
+                last_depth = 0;
+                continue;
+            }
+            if let Some(directive) = trimmed.strip_prefix('@') {
+                if let Err((offset, len, message)) =
+                    ret.add_directive(directive)
+                {
+                    errors.push(error((offset + 1, len, message)));
+                }
+                continue;
+            }
+            if let Some(declaration) = strip_keyword(trimmed, "participant") {
+                if let Err(message) = ret.add_participant(declaration) {
+                    errors.push(error((0, trimmed.len(), message)));
+                }
+                continue;
+            }
+            if let Some(declaration) = strip_keyword(trimmed, "box") {
+                if let Err(message) = ret.add_group(declaration) {
+                    errors.push(error((0, trimmed.len(), message)));
+                }
+                continue;
+            }
+            if strip_keyword(trimmed, "autonumber").is_some() {
+                if let Err((offset, len, message)) = ret.add_directive(trimmed)
+                {
+                    errors.push(error((offset, len, message)));
+                }
                 continue;
             }
 
-            let mut curr_component = parts[0].to_string();
-            curr_component.retain(|c: char| !c.is_whitespace());
-            let mut curr_func_call = parts.get(1).unwrap_or(&"").to_string();
-            curr_func_call.retain(|c: char| !c.is_whitespace());
+            let (kind, statement) = MessageKind::split(trimmed);
+            let kind_len = trimmed.len() - statement.len();
+            let parsed =
+                split_metadata(statement).and_then(|(path, metadata)| {
+                    split_statement(path, depth > 0, &options.separators)
+                        .map(|(component, func)| (component, func, metadata))
+                });
+            let (curr_component, curr_func_call, metadata) = match parsed {
+                Ok(parts) => parts,
+                Err((offset, len, message)) => {
+                    errors.push(error((kind_len + offset, len, message)));
+                    continue;
+                }
+            };
+            let curr_component = ret.resolve(&curr_component);
+            let curr_func_call = curr_func_call.unwrap_or_default();
 
-            ret.add_component_func(&curr_component, &curr_func_call);
+            if depth > last_depth {
+                indents.push(depth);
+                if !last_component.is_empty() {
+                    function_stack.push((last_component, last_depth));
+                }
+            } else if depth < last_depth {
+                while indents.last().is_some_and(|&level| level > depth) {
+                    indents.pop();
+                }
+                while function_stack
+                    .last()
+                    .is_some_and(|(_, level)| *level >= depth)
+                {
+                    function_stack.pop();
+                }
+                if indents.last() != Some(&depth) {
+                    errors.push(error((
+                        0,
+                        0,
+                        String::from(
+                            "unindent does not match any outer indentation \
+                             level",
+                        ),
+                    )));
+                    indents.push(depth);
+                }
+            }
+
+            if !curr_component.is_empty() {
+                ret.add_component_func(&curr_component, &curr_func_call);
+            }
 
-            let non_space_pos =
-                line.find(|c: char| !c.is_whitespace()).unwrap_or(0);
-            if non_space_pos > last_depth && !last_component.is_empty() {
-                function_stack.push((last_component, last_depth));
-            } else if non_space_pos < last_depth {
-                function_stack.pop();
+            // each statement takes the next number on its nesting level
+            if !curr_component.is_empty() {
+                numbers.resize(function_stack.len() + 1, 0);
+                numbers[function_stack.len()] += 1;
             }
 
             let (calling_component, _) = function_stack
@@ -56,33 +460,419 @@ impl CallGraph {
                 .unwrap_or(&(String::from(""), 0))
                 .clone();
             if !curr_component.is_empty() && !curr_func_call.is_empty() {
-                ret.func_calls.push((
-                    calling_component,
-                    (curr_component.clone(), curr_func_call.clone()),
-                ));
+                let message = if destroyed.contains(&curr_component) {
+                    Some("call to destroyed participant")
+                } else if kind == MessageKind::Create
+                    && used.contains(&curr_component)
+                {
+                    Some("create of participant already in use")
+                } else {
+                    None
+                };
+                if let Some(message) = message {
+                    let len = trimmed.len();
+                    errors.push(error((0, len, message.to_string())));
+                }
+                if kind == MessageKind::Destroy {
+                    destroyed.insert(curr_component.clone());
+                }
+                used.insert(calling_component.clone());
+                used.insert(curr_component.clone());
+                ret.func_calls.push(FunctionCall {
+                    caller: calling_component,
+                    callee: (curr_component.clone(), curr_func_call.clone()),
+                    number: numbers.clone(),
+                    kind,
+                    args: metadata.args,
+                    timing: metadata.timing,
+                    annotation: metadata.annotation,
+                });
             }
 
             last_component = curr_component;
-            last_depth = non_space_pos;
+            last_depth = depth;
+        }
+        ret.order_participants();
+        ret.group_components();
+
+        if errors.is_empty() {
+            Ok(ret)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Returns the label to draw for `component`: its declared display name,
+    /// then its alias, then the identifier itself, shortened to its last
+    /// [`ParseOptions::component_segments`].
+    pub fn label<'a>(&'a self, component: &'a str) -> &'a str {
+        match self.participants.get(component) {
+            Some(Participant {
+                display_name: Some(name),
+                ..
+            }) => name,
+            Some(Participant {
+                alias: Some(alias), ..
+            }) => alias,
+            _ => match self.options.component_segments {
+                Some(n) if n > 0 => {
+                    let segments =
+                        split_path(component, &self.options.separators);
+                    let first = segments.len().saturating_sub(n);
+                    &component[segments[first].0..]
+                }
+                _ => component,
+            },
+        }
+    }
+
+    /// Processes a `title`, `caption`, `footer`, `autonumber` or `style`
+    /// directive with its value.
+    fn add_directive(
+        &mut self,
+        directive: &str,
+    ) -> Result<(), StatementError> {
+        let name_len = directive
+            .find(char::is_whitespace)
+            .unwrap_or(directive.len());
+        let (name, value) = directive.split_at(name_len);
+        let value = value.trim();
+        let value_offset = directive.len() - value.len();
+        let invalid = |message| (value_offset, value.len(), message);
+        match name {
+            "title" => self.header.title = Some(value.to_string()),
+            "caption" => self.header.caption = Some(value.to_string()),
+            "footer" => self.header.footer = Some(value.to_string()),
+            "autonumber" if value.is_empty() => {
+                self.header.autonumber = Some(Numbering::Flat)
+            }
+            "autonumber" => {
+                self.header.autonumber = Some(value.parse().map_err(invalid)?)
+            }
+            "style" => {
+                self.header.style = Some(value.parse().map_err(invalid)?)
+            }
+            _ => {
+                return Err((
+                    0,
+                    name.len(),
+                    format!("unknown directive `@{}`", name),
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    /// Maps a participant alias back to its component identifier.
+    fn resolve(&self, component: &str) -> String {
+        self.aliases
+            .get(component)
+            .cloned()
+            .unwrap_or_else(|| component.to_string())
+    }
+
+    /// Processes a `"label" <identifier>... end` group declaration.
+    fn add_group(&mut self, declaration: &str) -> Result<(), String> {
+        let expected =
+            || String::from("expected `box \"label\" <participant>... end`");
+        let declaration = declaration
+            .strip_suffix("end")
+            .filter(|d| d.is_empty() || d.ends_with(char::is_whitespace))
+            .ok_or_else(expected)?;
+        let (label, names) = match declaration.strip_prefix('"') {
+            Some(quoted) => {
+                let end = quoted.find('"').ok_or_else(expected)?;
+                (&quoted[..end], &quoted[end + 1..])
+            }
+            None => declaration
+                .split_once(char::is_whitespace)
+                .ok_or_else(expected)?,
+        };
+
+        let mut components = Vec::new();
+        for name in split_words(names)? {
+            let name = name.replace('"', "");
+            let component = self.resolve(&name);
+            let other = self
+                .groups
+                .iter()
+                .find(|group| group.components.contains(&component));
+            if let Some(other) = other {
+                return Err(format!(
+                    "`{}` is already in box `{}`",
+                    name, other.label
+                ));
+            }
+            self.add_component_func(&component, "");
+            components.push(component);
+        }
+        if components.is_empty() {
+            return Err(expected());
+        }
+        self.groups.push(Group {
+            label: label.to_string(),
+            components,
+        });
+        Ok(())
+    }
+
+    /// Moves the declared participants ahead of the undeclared ones in
+    /// `components_in_order`, in the order of their declarations, even when
+    /// used in a call before being declared.
+    fn order_participants(&mut self) {
+        let declared = &self.declared;
+        let (mut order, undeclared): (Vec<String>, Vec<String>) = self
+            .components_in_order
+            .drain(..)
+            .partition(|c| declared.contains(c));
+        order.sort_by_key(|c| declared.iter().position(|d| d == c));
+        order.extend(undeclared);
+        self.components_in_order = order;
+    }
+
+    /// Moves the components of each group next to the first of them in
+    /// `components_in_order`.
+    fn group_components(&mut self) {
+        for group in &self.groups {
+            let order = &mut self.components_in_order;
+            let first = match order
+                .iter()
+                .position(|c| group.components.contains(c))
+            {
+                Some(first) => first,
+                None => continue,
+            };
+            let (grouped, others): (Vec<String>, Vec<String>) = order
+                .drain(first..)
+                .partition(|c| group.components.contains(c));
+            order.extend(grouped);
+            order.extend(others);
         }
+    }
+
+    /// Processes a `<identifier> [as <alias>] ["display name"]` declaration.
+    /// Declared participants are ordered before the undeclared ones.
+    fn add_participant(&mut self, declaration: &str) -> Result<(), String> {
+        let words = split_words(declaration)?;
+        let quoted = |word: &str| {
+            word.len() > 1 && word.starts_with('"') && word.ends_with('"')
+        };
+        // a quoted word after the identifier is the display name
+        let (names, display_name) = match words[..] {
+            [ref names @ .., last] if !names.is_empty() && quoted(last) => {
+                (names, Some(last[1..last.len() - 1].to_string()))
+            }
+            _ => (&words[..], None),
+        };
+
+        let unquote = |identifier: &str| identifier.replace('"', "");
+        let (component, alias) = match *names {
+            [component] => (unquote(component), None),
+            [component, "as", alias] => {
+                (unquote(component), Some(unquote(alias)))
+            }
+            _ => {
+                return Err(String::from(
+                    "expected `participant <identifier> [as <alias>] \
+                     [\"display name\"]`",
+                ))
+            }
+        };
 
-        ret
+        if let Some(alias) = &alias {
+            self.aliases.insert(alias.to_owned(), component.clone());
+        }
+        self.add_component_func(&component, "");
+        if !self.declared.contains(&component) {
+            self.declared.push(component.clone());
+        }
+        self.participants.insert(
+            component,
+            Participant {
+                alias,
+                display_name,
+            },
+        );
+        Ok(())
     }
 
     fn add_component_func(&mut self, component: &str, func: &str) {
         if !component.is_empty() && !self.components.contains_key(component) {
             self.components_in_order.push(component.to_string());
         }
-        let set = self
-            .components
-            .entry(component.to_owned())
-            .or_insert(HashSet::new());
+        let set = self.components.entry(component.to_owned()).or_default();
         if !func.is_empty() {
             set.insert(func.to_owned());
         }
     }
 }
 
+/// Splits `statement` to its component identifier, which may be a path of
+/// several segments, and function name, the last segment. A statement
+/// without function names the calling component of a section, and is only
+/// allowed when not `indented`.
+fn split_statement(
+    statement: &str,
+    indented: bool,
+    separators: &[String],
+) -> Result<(String, Option<String>), StatementError> {
+    let expected = || {
+        let separator = separators.first().map_or("::", |s| s.as_str());
+        format!(
+            "expected `Component{}function`, found `{}`",
+            separator, statement
+        )
+    };
+    let unquote = |identifier: &str| identifier.replace('"', "");
+    if statement.is_empty() {
+        return Ok((String::new(), None));
+    }
+    let segments = split_path(statement, separators);
+    if segments.iter().any(|(start, end)| start == end) {
+        return Err((0, statement.len(), expected()));
+    }
+    match segments[..] {
+        [_] if !indented => Ok((unquote(statement), None)),
+        [_] => Err((0, statement.len(), expected())),
+        [.., (_, component_end), (func_start, _)] => Ok((
+            unquote(&statement[..component_end]),
+            Some(unquote(&statement[func_start..])),
+        )),
+        [] => unreachable!(),
+    }
+}
+
+/// Splits the trailing `(args)`, `[timing]` and `# annotation` or
+/// `// annotation` from the qualified path in front of `statement`.
+fn split_metadata(
+    statement: &str,
+) -> Result<(&str, Metadata), StatementError> {
+    let mut quoted = false;
+    let path_end = statement
+        .find(|c: char| {
+            quoted ^= c == '"';
+            !quoted && (c.is_whitespace() || c == '(' || c == '[')
+        })
+        .unwrap_or(statement.len());
+    if quoted {
+        let quote = statement.rfind('"').unwrap_or(0);
+        return Err((
+            quote,
+            statement.len() - quote,
+            String::from("unclosed `\"`"),
+        ));
+    }
+    let (path, mut rest) = statement.split_at(path_end);
+    let offset = |rest: &str| statement.len() - rest.len();
+    let mut metadata = Metadata::default();
+
+    if rest.starts_with('(') {
+        let close = closing_paren(rest).ok_or_else(|| {
+            (offset(rest), rest.len(), String::from("unclosed `(`"))
+        })?;
+        metadata.args = Some(rest[1..close].trim().to_string());
+        rest = &rest[close + 1..];
+    }
+    rest = rest.trim_start();
+    if rest.starts_with('[') {
+        let close = rest.find(']').ok_or_else(|| {
+            (offset(rest), rest.len(), String::from("unclosed `[`"))
+        })?;
+        metadata.timing = Some(rest[1..close].trim().to_string());
+        rest = rest[close + 1..].trim_start();
+    }
+    if let Some(annotation) =
+        rest.strip_prefix("//").or_else(|| rest.strip_prefix('#'))
+    {
+        metadata.annotation = Some(annotation.trim().to_string());
+    } else if !rest.is_empty() {
+        return Err((
+            offset(rest),
+            rest.len(),
+            format!(
+                "unexpected `{}`, expected `(args)`, `[timing]` or \
+                 `# annotation`",
+                rest
+            ),
+        ));
+    }
+    Ok((path, metadata))
+}
+
+/// Returns the byte index of the `)` closing the `(` in front of `text`.
+fn closing_paren(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Returns the byte ranges of the segments of `path` between `separators`,
+/// matching the longest separator first at each position. Separators within
+/// `"` quotes are part of the segment.
+fn split_path(path: &str, separators: &[String]) -> Vec<(usize, usize)> {
+    let mut segments = Vec::new();
+    let mut start = 0;
+    let mut i = 0;
+    let mut quoted = false;
+    while i < path.len() {
+        quoted ^= path[i..].starts_with('"');
+        let separator = separators
+            .iter()
+            .filter(|separator| {
+                !quoted
+                    && !separator.is_empty()
+                    && path[i..].starts_with(*separator)
+            })
+            .max_by_key(|separator| separator.len());
+        match separator {
+            Some(separator) => {
+                segments.push((start, i));
+                i += separator.len();
+                start = i;
+            }
+            None => i += path[i..].chars().next().map_or(1, char::len_utf8),
+        }
+    }
+    segments.push((start, path.len()));
+    segments
+}
+
+/// Splits `text` into words at the whitespace outside `"` quotes, keeping
+/// the quotes in the words.
+fn split_words(text: &str) -> Result<Vec<&str>, String> {
+    let mut words = Vec::new();
+    let mut start = None;
+    let mut quoted = false;
+    for (i, c) in text.char_indices() {
+        if c.is_whitespace() && !quoted {
+            if let Some(start) = start.take() {
+                words.push(&text[start..i]);
+            }
+        } else {
+            start.get_or_insert(i);
+            quoted ^= c == '"';
+        }
+    }
+    if quoted {
+        return Err(String::from("unclosed `\"`"));
+    }
+    if let Some(start) = start {
+        words.push(&text[start..]);
+    }
+    Ok(words)
+}
+
 // TODO add unit tests
 #[cfg(test)]
 mod test {
@@ -94,7 +884,7 @@ mod test {
         let txt =
             fs::read_to_string("./test/callgraph_multi_section.txt").unwrap();
 
-        let callgraph = CallGraph::new(&txt);
+        let callgraph = CallGraph::new(&txt).unwrap();
 
         let a_func = callgraph.components.get("ClassA").unwrap();
         assert!(a_func.contains("func_1"));
@@ -116,10 +906,10 @@ mod test {
             ("ClassC", ("ClassB", "func_3")),
         ];
         assert_eq!(func_calls.len(), callgraph.func_calls.len());
-        for i in 0..func_calls.len() {
-            assert_eq!(func_calls[i].0, callgraph.func_calls[i].0);
-            assert_eq!(func_calls[i].1 .0, callgraph.func_calls[i].1 .0);
-            assert_eq!(func_calls[i].1 .1, callgraph.func_calls[i].1 .1);
+        for (expected, f) in func_calls.iter().zip(&callgraph.func_calls) {
+            assert_eq!(expected.0, f.caller);
+            assert_eq!(expected.1 .0, f.callee.0);
+            assert_eq!(expected.1 .1, f.callee.1);
         }
     }
 
@@ -128,7 +918,7 @@ mod test {
         let txt = fs::read_to_string("./test/callgraph_multi_section_2.txt")
             .unwrap();
 
-        let callgraph = CallGraph::new(&txt);
+        let callgraph = CallGraph::new(&txt).unwrap();
 
         println!("{:?}", callgraph);
 
@@ -177,10 +967,300 @@ mod test {
             ("ClassF", ("ClassE", "func_3")),
         ];
         assert_eq!(func_calls.len(), callgraph.func_calls.len());
-        for i in 0..func_calls.len() {
-            assert_eq!(func_calls[i].0, callgraph.func_calls[i].0);
-            assert_eq!(func_calls[i].1 .0, callgraph.func_calls[i].1 .0);
-            assert_eq!(func_calls[i].1 .1, callgraph.func_calls[i].1 .1);
+        for (expected, f) in func_calls.iter().zip(&callgraph.func_calls) {
+            assert_eq!(expected.0, f.caller);
+            assert_eq!(expected.1 .0, f.callee.0);
+            assert_eq!(expected.1 .1, f.callee.1);
+        }
+    }
+
+    #[test]
+    fn test_groups() {
+        let txt = fs::read_to_string("./test/callgraph_groups.txt").unwrap();
+
+        let callgraph = CallGraph::new(&txt).unwrap();
+
+        assert_eq!(
+            callgraph.groups,
+            [Group {
+                label: String::from("Backend"),
+                components: vec![
+                    String::from("Orders"),
+                    String::from("Payments")
+                ],
+            }]
+        );
+        assert_eq!(
+            callgraph.components_in_order,
+            ["Client", "Orders", "Payments", "Inventory"]
+        );
+
+        let callgraph = CallGraph::new(
+            "Client::checkout\n  Orders::place_order\n\
+                            box Backend Client Orders end\n",
+        )
+        .unwrap();
+        assert_eq!(callgraph.components_in_order, ["Client", "Orders"]);
+
+        let callgraph = CallGraph::new(
+            "box Backend \"My Service\" Other end\n\"My Service\"::run\n",
+        )
+        .unwrap();
+        assert_eq!(callgraph.groups[0].components, ["My Service", "Other"]);
+        assert_eq!(callgraph.components_in_order, ["My Service", "Other"]);
+
+        let errors = CallGraph::new(
+            "box Backend Orders end\nbox Other Orders end\nbox Other A\n",
+        )
+        .unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].message, "`Orders` is already in box `Backend`");
+    }
+
+    #[test]
+    fn test_participants() {
+        let txt =
+            fs::read_to_string("./test/callgraph_participants.txt").unwrap();
+
+        let callgraph = CallGraph::new(&txt).unwrap();
+
+        assert_eq!(
+            callgraph.components_in_order,
+            ["Client", "OrderService", "PaymentGatewayClientImpl"]
+        );
+        assert_eq!(
+            callgraph.label("PaymentGatewayClientImpl"),
+            "Payment Gateway"
+        );
+        assert_eq!(callgraph.label("OrderService"), "Orders");
+        assert_eq!(callgraph.label("Client"), "Client");
+
+        let func_calls = [
+            ("", ("Client", "checkout")),
+            ("Client", ("OrderService", "place_order")),
+            ("OrderService", ("PaymentGatewayClientImpl", "charge")),
+            ("Client", ("Client", "render_receipt")),
+        ];
+        assert_eq!(func_calls.len(), callgraph.func_calls.len());
+        for (expected, f) in func_calls.iter().zip(&callgraph.func_calls) {
+            assert_eq!(expected.0, f.caller);
+            assert_eq!(expected.1 .0, f.callee.0);
+            assert_eq!(expected.1 .1, f.callee.1);
         }
     }
+
+    #[test]
+    fn test_participant_order() {
+        let txt = "\
+Client::checkout
+  Orders::place_order
+    Payments::charge
+participant Payments
+participant Orders
+";
+        let callgraph = CallGraph::new(txt).unwrap();
+        assert_eq!(
+            callgraph.components_in_order,
+            ["Payments", "Orders", "Client"]
+        );
+
+        let errors = CallGraph::new("participant A as \"Foo\n").unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "unclosed `\"`");
+
+        let callgraph = CallGraph::new(
+            "participant \"My Service\" as MS \"Service\"\n\
+             participant \"Other Service\"\nMS::run\n",
+        )
+        .unwrap();
+        assert_eq!(
+            callgraph.components_in_order,
+            ["My Service", "Other Service"]
+        );
+        assert_eq!(callgraph.label("My Service"), "Service");
+        assert_eq!(callgraph.label("Other Service"), "Other Service");
+    }
+
+    #[test]
+    fn test_hierarchical_number() {
+        let txt =
+            fs::read_to_string("./test/callgraph_multi_section.txt").unwrap();
+
+        let callgraph = CallGraph::new(&txt).unwrap();
+
+        let numbers: Vec<&[usize]> = callgraph
+            .func_calls
+            .iter()
+            .map(|f| f.number.as_slice())
+            .collect();
+        assert_eq!(
+            numbers,
+            [&[1][..], &[1, 1], &[1, 1, 1], &[1, 1, 2], &[1, 2], &[2, 1]]
+        );
+    }
+
+    #[test]
+    fn test_message_kind() {
+        let txt =
+            fs::read_to_string("./test/callgraph_message_kind.txt").unwrap();
+
+        let callgraph = CallGraph::new(&txt).unwrap();
+
+        assert_eq!(callgraph.components_in_order, ["ClassA", "ClassB"]);
+        let kinds: Vec<MessageKind> =
+            callgraph.func_calls.iter().map(|f| f.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                MessageKind::Sync,
+                MessageKind::Create,
+                MessageKind::Async,
+                MessageKind::Destroy
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let txt = "ClassA::func_1\n  foo\n  ClassB::func 2\n";
+
+        let errors = CallGraph::new(txt).unwrap_err();
+
+        assert_eq!(errors.len(), 2);
+        assert_eq!((errors[0].line, errors[0].column), (2, 3));
+        assert_eq!(errors[0].len, 3);
+        assert_eq!(
+            errors[0].message,
+            "expected `Component::function`, found `foo`"
+        );
+        assert_eq!(errors[0].snippet, "  foo");
+        assert_eq!((errors[1].line, errors[1].column), (3, 16));
+        assert_eq!(errors[1].len, 1);
+
+        let txt = "A::run\n  B::get\n  create B::new\n  destroy C::drop\n\
+                   \x20 C::get\n";
+
+        let errors = CallGraph::new(txt).unwrap_err();
+
+        let errors: Vec<(usize, &str)> = errors
+            .iter()
+            .map(|e| (e.line, e.message.as_str()))
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                (3, "create of participant already in use"),
+                (5, "call to destroyed participant"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dedent() {
+        let txt = "A::a\n\tB::b\n\t\tC::c\n\t\t\tD::d\n    B::e\n";
+
+        let callgraph = CallGraph::new(txt).unwrap();
+
+        let callers: Vec<&str> = callgraph
+            .func_calls
+            .iter()
+            .map(|f| f.caller.as_str())
+            .collect();
+        assert_eq!(callers, ["", "A", "B", "C", "A"]);
+        assert_eq!(callgraph.func_calls[4].number, [1, 2]);
+
+        let txt = "A::a\n    B::b\n        C::c\n  B::e\n";
+
+        let errors = CallGraph::new(txt).unwrap_err();
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!((errors[0].line, errors[0].column), (4, 3));
+    }
+
+    #[test]
+    fn test_qualified_path() {
+        let txt = "main\n  std::collections::HashMap::insert\n";
+
+        let callgraph = CallGraph::new(txt).unwrap();
+
+        let callee = &callgraph.func_calls[0].callee;
+        assert_eq!(callee.0, "std::collections::HashMap");
+        assert_eq!(callee.1, "insert");
+        assert!(CallGraph::new("main\n  a.b->c\n").is_err());
+
+        let options = ParseOptions {
+            separators: vec![String::from("."), String::from("->")],
+            component_segments: Some(1),
+            ..Default::default()
+        };
+        let callgraph =
+            CallGraph::with_options("main\n  a.b->c\n", &options).unwrap();
+
+        let callee = &callgraph.func_calls[0].callee;
+        assert_eq!((callee.0.as_str(), callee.1.as_str()), ("a.b", "c"));
+        assert_eq!(callgraph.label("a.b"), "b");
+    }
+
+    #[test]
+    fn test_metadata() {
+        let txt = "A::a\n  B::get(id, (1, 2)) [12ms] // cached\n  B::put()\n";
+
+        let callgraph = CallGraph::new(txt).unwrap();
+
+        let get = &callgraph.func_calls[1];
+        assert_eq!(get.callee.1, "get");
+        assert_eq!(get.args.as_deref(), Some("id, (1, 2)"));
+        assert_eq!(get.timing.as_deref(), Some("12ms"));
+        assert_eq!(get.annotation.as_deref(), Some("cached"));
+        let put = &callgraph.func_calls[2];
+        assert_eq!(put.args.as_deref(), Some(""));
+        assert_eq!(
+            (put.timing.as_ref(), put.annotation.as_ref()),
+            (None, None)
+        );
+
+        assert!(CallGraph::new("A::a\n  B::get(id\n").is_err());
+    }
+
+    #[test]
+    fn test_directives() {
+        let txt =
+            fs::read_to_string("./test/callgraph_directives.txt").unwrap();
+
+        let callgraph = CallGraph::new(&txt).unwrap();
+
+        assert_eq!(callgraph.header.title.as_deref(), Some("Checkout"));
+        assert_eq!(callgraph.header.autonumber, Some(Numbering::Flat));
+        assert_eq!(callgraph.header.style, Some(Style::Ascii));
+        assert_eq!(
+            callgraph.components_in_order,
+            ["Web Client", "Order Service"]
+        );
+        assert_eq!(callgraph.func_calls[1].caller, "Web Client");
+        assert_eq!(callgraph.func_calls[2].caller, "Order Service");
+
+        let errors = CallGraph::new("@colour red\n\"A::b\n").unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert_eq!((errors[0].column, errors[0].len), (2, 6));
+    }
+
+    #[test]
+    fn test_markers() {
+        let txt = fs::read_to_string("./test/callgraph_markers.txt").unwrap();
+
+        let callgraph = CallGraph::new(&txt).unwrap();
+
+        assert_eq!(
+            callgraph.markers,
+            [
+                (0, Marker::Divider(String::from("Checkout"))),
+                (3, Marker::Delay(Some(String::from("3 days later")))),
+                (3, Marker::Divider(String::from("Delivery"))),
+                (5, Marker::Delay(None)),
+            ]
+        );
+        // the divider ends the section of `Client`
+        assert_eq!(callgraph.func_calls[3].caller, "");
+        assert_eq!(callgraph.func_calls[4].caller, "Orders");
+    }
 }
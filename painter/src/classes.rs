@@ -0,0 +1,417 @@
+//! This module includes the [`ClassDiagramLayout`] type, which holds the
+//! geometry of the class diagram of a [`CallGraph`]: a UML class-like box
+//! per component listing its functions, packed in a grid, with arrows from
+//! the components to the ones they call.
+use crate::callgraph::CallGraph;
+use crate::collaboration::{assign_tracks, EdgeLayout};
+use crate::config::PainterConfig;
+use crate::layout::TextLayout;
+use canvas::Rectangle;
+use std::cmp::max;
+use std::collections::HashMap;
+
+/// The UML class-like box of a component.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClassLayout {
+    pub rec: Rectangle,
+    /// Name of the component, in the compartment on top.
+    pub name: TextLayout,
+    /// Row of the border between the compartments.
+    pub divider: usize,
+    /// Functions of the component in the order of their first call, in the
+    /// compartment below.
+    pub functions: TextLayout,
+}
+
+/// Geometry of the class diagram of a [`CallGraph`], to be drawn by a
+/// [`ClassRenderer`](crate::render::ClassRenderer).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClassDiagramLayout {
+    /// Boxes of the components, in the order of `components_in_order`.
+    pub classes: Vec<ClassLayout>,
+    /// Arrows from the calling components to the called ones.
+    pub edges: Vec<EdgeLayout>,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl ClassDiagramLayout {
+    /// Lays out the components of `callgraph` row by row in a grid with as
+    /// many columns as fit in `class_grid_width` of the config, if any, with
+    /// the arrows routed through the gaps between the rows and the columns.
+    pub fn new(callgraph: &CallGraph, config: &PainterConfig) -> Self {
+        let components: Vec<&str> = callgraph
+            .components_in_order
+            .iter()
+            .map(|c| c.as_str())
+            .filter(|c| callgraph.components.contains_key(*c))
+            .collect();
+        let index: HashMap<&str, usize> = components
+            .iter()
+            .enumerate()
+            .map(|(i, &c)| (c, i))
+            .collect();
+
+        let mut functions: Vec<Vec<&str>> = vec![Vec::new(); components.len()];
+        for f in &callgraph.func_calls {
+            let (component, function) = &f.callee;
+            if let Some(&i) = index.get(component.as_str()) {
+                if !function.is_empty()
+                    && !functions[i].contains(&function.as_str())
+                {
+                    functions[i].push(function);
+                }
+            }
+        }
+        let classes: Vec<Class> = components
+            .iter()
+            .zip(functions)
+            .map(|(component, functions)| Class {
+                name: callgraph.label(component).to_string(),
+                functions: functions.iter().map(|f| f.to_string()).collect(),
+            })
+            .collect();
+        let edges: Vec<(usize, usize)> = callgraph
+            .collaborations()
+            .iter()
+            .map(|c| (index[c.caller.as_str()], index[c.callee.as_str()]))
+            .collect();
+
+        let mut columns = max(classes.len(), 1);
+        loop {
+            let layout = Self::with_columns(&classes, &edges, columns, config);
+            let fits =
+                config.class_grid_width.is_none_or(|w| layout.width <= w);
+            if fits || columns == 1 {
+                return layout;
+            }
+            columns -= 1;
+        }
+    }
+
+    /// Lays out `classes` in a grid of `columns` columns, with the arrows of
+    /// `edges` between them.
+    fn with_columns(
+        classes: &[Class],
+        edges: &[(usize, usize)],
+        columns: usize,
+        config: &PainterConfig,
+    ) -> Self {
+        let cells: Vec<(usize, usize)> = (0..classes.len())
+            .map(|i| (i / columns, i % columns))
+            .collect();
+        let rows = classes.len().div_ceil(columns);
+        let routes: Vec<Route> = edges
+            .iter()
+            .map(|&(a, b)| Route::new(cells[a], cells[b]))
+            .collect();
+
+        // the arrows leaving and entering the top and bottom sides of each
+        // box, spread two columns apart in the order of the columns at their
+        // other end
+        let mut sides: HashMap<(usize, bool), Vec<End>> = HashMap::new();
+        for (e, (&(a, b), route)) in edges.iter().zip(&routes).enumerate() {
+            sides
+                .entry((a, route.leaves_down))
+                .or_default()
+                .push((cells[b].1, e, true));
+            sides
+                .entry((b, !route.enters_down))
+                .or_default()
+                .push((cells[a].1, e, false));
+        }
+        let mut widths: Vec<usize> =
+            classes.iter().map(Class::width).collect();
+        for (&(class, _), ends) in &mut sides {
+            ends.sort();
+            widths[class] = max(widths[class], 2 * ends.len() + 1);
+        }
+        // column of each arrow from the left of the box it leaves and the
+        // one it enters
+        let mut ports = vec![(0, 0); edges.len()];
+        for (&(class, _), ends) in &sides {
+            let center = widths[class] / 2;
+            for (i, &(_, e, leaving)) in ends.iter().enumerate() {
+                let column = center + 2 * i + 1 - ends.len();
+                if leaving {
+                    ports[e].0 = column;
+                } else {
+                    ports[e].1 = column;
+                }
+            }
+        }
+
+        // the gaps between the columns, with a track for each arrow going
+        // past rows of boxes
+        let mut gap_tracks = vec![Vec::new(); columns + 1];
+        for (e, route) in routes.iter().enumerate() {
+            if let Some(gap) = route.gap {
+                gap_tracks[gap].push(e);
+            }
+        }
+        let mut column_widths = vec![0; columns];
+        for (i, &(_, column)) in cells.iter().enumerate() {
+            column_widths[column] = max(column_widths[column], widths[i]);
+        }
+        let mut lefts = Vec::with_capacity(columns);
+        // column of the vertical track of each arrow going around
+        let mut verticals = vec![0; edges.len()];
+        let mut x = 0;
+        for (gap, tracks) in gap_tracks.iter().enumerate() {
+            let needed = 2 * tracks.len() + 1;
+            let width = match gap {
+                0 if tracks.is_empty() => 0,
+                _ if gap == columns && tracks.is_empty() => 0,
+                0 => needed,
+                _ if gap == columns => needed,
+                _ => max(config.participant_gap, needed),
+            };
+            let first = x + (width + 2 - needed) / 2;
+            for (i, &e) in tracks.iter().enumerate() {
+                verticals[e] = first + 2 * i;
+            }
+            x += width;
+            if gap < columns {
+                lefts.push(x);
+                x += column_widths[gap];
+            }
+        }
+        let width = x;
+        let class_left = |i: usize| {
+            let column = cells[i].1;
+            lefts[column] + (column_widths[column] - widths[i]) / 2
+        };
+
+        // the horizontal parts of the arrows in the gaps between the rows,
+        // from and to their columns
+        let mut channels = vec![Vec::new(); rows + 1];
+        for (e, (&(a, b), route)) in edges.iter().zip(&routes).enumerate() {
+            let from = class_left(a) + ports[e].0;
+            let to = class_left(b) + ports[e].1;
+            match route.gap {
+                Some(_) => {
+                    channels[route.first].push((e, 0, (from, verticals[e])));
+                    channels[route.last].push((e, 1, (verticals[e], to)));
+                }
+                None if from != to => {
+                    channels[route.first].push((e, 0, (from, to)))
+                }
+                None => {}
+            }
+        }
+        // row of each horizontal part of each arrow
+        let mut track_rows = vec![[0, 0]; edges.len()];
+        let mut row_heights = vec![0; rows];
+        for (i, class) in classes.iter().enumerate() {
+            let row = cells[i].0;
+            row_heights[row] = max(row_heights[row], class.height());
+        }
+        let mut tops = Vec::with_capacity(rows);
+        let mut y = 0;
+        for (channel, parts) in channels.iter().enumerate() {
+            let spans: Vec<(usize, usize)> =
+                parts.iter().map(|&(_, _, span)| span).collect();
+            let (tracks, count) = assign_tracks(&spans);
+            for (&(e, part, _), track) in parts.iter().zip(tracks) {
+                track_rows[e][part] = y + 1 + track;
+            }
+            y += match count {
+                0 if channel == 0 || channel == rows => 0,
+                0 => 1,
+                _ => count + 2,
+            };
+            if channel < rows {
+                tops.push(y);
+                y += row_heights[channel];
+            }
+        }
+        let height = y;
+
+        let classes: Vec<ClassLayout> = classes
+            .iter()
+            .enumerate()
+            .map(|(i, class)| {
+                class.layout(class_left(i), tops[cells[i].0], widths[i])
+            })
+            .collect();
+        let edges = edges
+            .iter()
+            .zip(&routes)
+            .enumerate()
+            .map(|(e, (&(a, b), route))| {
+                let (a, b) = (&classes[a].rec, &classes[b].rec);
+                let from = a.left + ports[e].0;
+                let to = b.left + ports[e].1;
+                let leaving = if route.leaves_down { a.bottom } else { a.top };
+                let entering =
+                    if route.enters_down { b.top } else { b.bottom };
+                let [first, last] = track_rows[e];
+                let mut points = vec![(leaving, from)];
+                match route.gap {
+                    Some(_) => points.extend([
+                        (first, from),
+                        (first, verticals[e]),
+                        (last, verticals[e]),
+                        (last, to),
+                    ]),
+                    None if from != to => {
+                        points.extend([(first, from), (first, to)])
+                    }
+                    None => {}
+                }
+                points.push((entering, to));
+                EdgeLayout { points }
+            })
+            .collect();
+
+        ClassDiagramLayout {
+            classes,
+            edges,
+            width,
+            height,
+        }
+    }
+}
+
+/// A component with its functions.
+struct Class {
+    name: String,
+    functions: Vec<String>,
+}
+
+impl Class {
+    /// Returns the width of the box, its texts being a space away from its
+    /// sides.
+    fn width(&self) -> usize {
+        let texts = self.functions.iter().chain(Some(&self.name));
+        texts.map(|t| t.chars().count()).max().unwrap_or(0) + 4
+    }
+
+    /// Returns the height of the box: its borders, name and functions.
+    fn height(&self) -> usize {
+        self.functions.len() + 4
+    }
+
+    fn layout(&self, left: usize, top: usize, width: usize) -> ClassLayout {
+        let indent = (width - 2 - self.name.chars().count()) / 2;
+        ClassLayout {
+            rec: Rectangle {
+                left,
+                right: left + width - 1,
+                top,
+                bottom: top + self.height() - 1,
+            },
+            name: TextLayout {
+                top: top + 1,
+                left: left + 1 + indent,
+                lines: vec![self.name.clone()],
+            },
+            divider: top + 2,
+            functions: TextLayout {
+                top: top + 3,
+                left: left + 2,
+                lines: self.functions.clone(),
+            },
+        }
+    }
+}
+
+/// An end of an arrow on a side of a box: the grid column of the box at its
+/// other end, the arrow, and whether it leaves the box.
+type End = (usize, usize, bool);
+
+/// The way of an arrow through the gaps of the grid.
+struct Route {
+    /// Whether the arrow leaves its box from the bottom, or else the top.
+    leaves_down: bool,
+    /// Whether the arrow enters its box from the top, or else the bottom.
+    enters_down: bool,
+    /// Gap between the rows, numbered from the top one, the arrow goes
+    /// along from the box it leaves.
+    first: usize,
+    /// Gap between the columns the arrow goes along past the rows of boxes
+    /// between its ends, if any.
+    gap: Option<usize>,
+    /// Gap between the rows the arrow goes along to the box it enters.
+    last: usize,
+}
+
+impl Route {
+    /// Returns the route of an arrow from the box at the `(row, column)`
+    /// cell `from` to the one at `to`.
+    fn new(from: (usize, usize), to: (usize, usize)) -> Self {
+        // a box on the same row is entered from the bottom, like the boxes
+        // above
+        let leaves_down = to.0 >= from.0;
+        let enters_down = to.0 > from.0;
+        let first = if leaves_down { from.0 + 1 } else { from.0 };
+        let last = if enters_down { to.0 } else { to.0 + 1 };
+        let gap = if first == last {
+            None
+        } else if to.1 < from.1 {
+            Some(from.1)
+        } else {
+            Some(from.1 + 1)
+        };
+        Route {
+            leaves_down,
+            enters_down,
+            first,
+            gap,
+            last,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_class_diagram_layout() {
+        let txt =
+            fs::read_to_string("./test/callgraph_collaboration.txt").unwrap();
+        let callgraph = CallGraph::new(&txt).unwrap();
+
+        let layout =
+            ClassDiagramLayout::new(&callgraph, &PainterConfig::default());
+        let functions: Vec<&[String]> = layout
+            .classes
+            .iter()
+            .map(|c| c.functions.lines.as_slice())
+            .collect();
+        assert_eq!(
+            functions,
+            [
+                &["checkout", "render_receipt"][..],
+                &["verify", "debit", "credit"],
+                &["place_order", "on_paid", "cancel"],
+                &["reserve"],
+                &["charge", "refund", "void"],
+            ]
+        );
+        let tops: Vec<usize> =
+            layout.classes.iter().map(|c| c.rec.top).collect();
+        assert_eq!(tops, [0, 0, 0, 0, 0]);
+
+        // two columns fit in 40
+        let layout = ClassDiagramLayout::new(
+            &callgraph,
+            &PainterConfig {
+                class_grid_width: Some(40),
+                ..Default::default()
+            },
+        );
+        assert!(layout.width <= 40);
+        let rows: Vec<(usize, usize)> = layout
+            .classes
+            .iter()
+            .map(|c| (c.rec.top, c.rec.left))
+            .collect();
+        assert_eq!(rows[0].0, rows[1].0);
+        assert_eq!(rows[2].0, rows[3].0);
+        assert!(rows[0].0 < rows[2].0 && rows[2].0 < rows[4].0);
+        assert!(rows[0].1 < rows[1].1 && rows[2].1 < rows[3].1);
+    }
+}
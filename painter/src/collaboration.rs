@@ -0,0 +1,672 @@
+//! This module includes the [`CollaborationLayout`] type, which holds the
+//! geometry of the collaboration diagram of a [`CallGraph`]: a box per
+//! component and an arrow per pair of calling and called components, laid
+//! out in layers from the callers down to the callees.
+use crate::callgraph::CallGraph;
+use crate::config::PainterConfig;
+use crate::layout::{BoxLayout, TextLayout};
+use canvas::Rectangle;
+use std::cmp::{max, min};
+use std::collections::HashMap;
+
+/// Columns between two nodes of a layer, unless both are boxes.
+const NODE_GAP: usize = 2;
+
+/// Barycenter sweeps over the layers to reduce the crossings of the edges.
+const ORDER_SWEEPS: usize = 8;
+
+/// Sweeps over the layers to pull the nodes in line with their neighbours,
+/// the last one going down.
+const PLACE_SWEEPS: usize = 7;
+
+/// The calls of a component to another one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Collaboration {
+    pub caller: String,
+    pub callee: String,
+    /// The functions called, in the order of their first call, with their
+    /// number of calls.
+    pub functions: Vec<(String, usize)>,
+}
+
+impl CallGraph {
+    /// Returns the calls between distinct components, grouped by calling
+    /// and called component in the order of their first call. The calls
+    /// from outside are left out.
+    pub fn collaborations(&self) -> Vec<Collaboration> {
+        let mut collaborations: Vec<Collaboration> = Vec::new();
+        for f in &self.func_calls {
+            let (callee, function) = &f.callee;
+            if f.caller.is_empty() || f.caller == *callee {
+                continue;
+            }
+            let found = collaborations
+                .iter()
+                .position(|c| c.caller == f.caller && c.callee == *callee);
+            let i = match found {
+                Some(i) => i,
+                None => {
+                    collaborations.push(Collaboration {
+                        caller: f.caller.clone(),
+                        callee: callee.clone(),
+                        functions: Vec::new(),
+                    });
+                    collaborations.len() - 1
+                }
+            };
+            let functions = &mut collaborations[i].functions;
+            match functions.iter_mut().find(|(name, _)| name == function) {
+                Some((_, count)) => *count += 1,
+                None => functions.push((function.clone(), 1)),
+            }
+        }
+        collaborations
+    }
+}
+
+/// An orthogonal arrow from the border of a box to the border of another
+/// one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EdgeLayout {
+    /// The ends and bends of the arrow as `(row, column)`, from the calling
+    /// box to the called one.
+    pub points: Vec<(usize, usize)>,
+}
+
+/// Geometry of the collaboration diagram of a [`CallGraph`], to be drawn by
+/// a [`CollaborationRenderer`](crate::render::CollaborationRenderer).
+#[derive(Debug, Clone, PartialEq)]
+pub struct CollaborationLayout {
+    /// Boxes of the components.
+    pub boxes: Vec<BoxLayout>,
+    /// Arrows from the calling components to the called ones.
+    pub edges: Vec<EdgeLayout>,
+    /// Labels of the arrows, listing the functions called with their
+    /// number of calls, padded to cover the arrows passing through them.
+    pub labels: Vec<TextLayout>,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl CollaborationLayout {
+    /// Lays out the components of `callgraph` following the Sugiyama
+    /// method: the cycles are broken by reversing edges, the components are
+    /// layered by their longest path from the ones calling, with a layer for
+    /// the labels and dummy nodes for the edges spanning several layers in
+    /// between, then the nodes are ordered to reduce the crossings, and
+    /// placed in line with their neighbours.
+    pub fn new(callgraph: &CallGraph, config: &PainterConfig) -> Self {
+        let components: Vec<&str> = callgraph
+            .components_in_order
+            .iter()
+            .map(|c| c.as_str())
+            .filter(|c| callgraph.components.contains_key(*c))
+            .collect();
+        let index: HashMap<&str, usize> = components
+            .iter()
+            .enumerate()
+            .map(|(i, &c)| (c, i))
+            .collect();
+        let collaborations = callgraph.collaborations();
+        let edges: Vec<(usize, usize)> = collaborations
+            .iter()
+            .map(|c| (index[c.caller.as_str()], index[c.callee.as_str()]))
+            .collect();
+        let reversed = back_edges(components.len(), &edges);
+        let down: Vec<(usize, usize)> = edges
+            .iter()
+            .zip(&reversed)
+            .map(|(&(a, b), &r)| if r { (b, a) } else { (a, b) })
+            .collect();
+        let ranks = ranks(components.len(), &down);
+
+        let mut builder = Builder {
+            nodes: Vec::new(),
+            segments: Vec::new(),
+            order: Vec::new(),
+            left: Vec::new(),
+            ports: Vec::new(),
+        };
+        for (i, component) in components.iter().enumerate() {
+            builder.nodes.push(Node {
+                kind: NodeKind::Component,
+                layer: 2 * ranks[i],
+                width: callgraph.label(component).chars().count() + 2,
+                height: 3,
+            });
+        }
+        let mut labels = Vec::new();
+        let mut chains = Vec::new();
+        for (c, &(upper, lower)) in collaborations.iter().zip(&down) {
+            let lines: Vec<String> = c
+                .functions
+                .iter()
+                .map(|(function, count)| format!("{} ×{}", function, count))
+                .collect();
+            let width = lines.iter().map(|l| l.chars().count()).max();
+            let (top, bottom) =
+                (builder.nodes[upper].layer, builder.nodes[lower].layer);
+            let mut chain = vec![upper];
+            for layer in top + 1..bottom {
+                let node = if layer == top + 1 {
+                    labels.push((builder.nodes.len(), lines.clone()));
+                    Node {
+                        kind: NodeKind::Label,
+                        layer,
+                        width: max(width.unwrap_or(0), 1),
+                        height: lines.len(),
+                    }
+                } else {
+                    Node {
+                        kind: NodeKind::Dummy,
+                        layer,
+                        width: 1,
+                        height: 0,
+                    }
+                };
+                chain.push(builder.nodes.len());
+                builder.nodes.push(node);
+            }
+            chain.push(lower);
+            chains.push(chain);
+        }
+        for chain in &chains {
+            for pair in chain.windows(2) {
+                builder.segments.push((pair[0], pair[1]));
+            }
+        }
+
+        let layers = builder.nodes.iter().map(|n| n.layer + 1).max();
+        builder.order = vec![Vec::new(); layers.unwrap_or(0)];
+        for (i, node) in builder.nodes.iter().enumerate() {
+            builder.order[node.layer].push(i);
+        }
+        builder.order_layers();
+        builder.layout_ports();
+        builder.place_nodes(config.participant_gap);
+
+        // rows of the layers, and of the tracks of the bent segments below
+        // each layer
+        let mut tops = Vec::with_capacity(builder.order.len());
+        let mut tracks = vec![0; builder.segments.len()];
+        let mut top = 0;
+        for layer in &builder.order {
+            let height = layer.iter().map(|&n| builder.nodes[n].height).max();
+            let bottom = top + height.unwrap_or(0);
+            tops.push(top);
+            let count = builder.layout_tracks(layer, bottom + 1, &mut tracks);
+            top = bottom + count + 2;
+        }
+
+        let boxes = components
+            .iter()
+            .enumerate()
+            .map(|(i, component)| {
+                let label = callgraph.label(component);
+                let node = &builder.nodes[i];
+                let left = builder.left[i] as usize;
+                let top = tops[node.layer];
+                let indent = (node.width - 2 - label.chars().count()) / 2;
+                BoxLayout {
+                    rec: Rectangle {
+                        left,
+                        right: left + node.width - 1,
+                        top,
+                        bottom: top + 2,
+                    },
+                    label: TextLayout {
+                        top: top + 1,
+                        left: left + 1 + indent,
+                        lines: vec![label.to_string()],
+                    },
+                }
+            })
+            .collect::<Vec<_>>();
+        let labels = labels
+            .into_iter()
+            .map(|(n, lines)| {
+                let width = builder.nodes[n].width;
+                TextLayout {
+                    top: tops[builder.nodes[n].layer],
+                    left: builder.left[n] as usize,
+                    lines: lines
+                        .iter()
+                        .map(|l| format!("{:<width$}", l, width = width))
+                        .collect(),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let mut s = 0;
+        let mut edge_layouts = Vec::with_capacity(chains.len());
+        for (chain, &reversed) in chains.iter().zip(&reversed) {
+            let (upper, lower) = (chain[0], chain[chain.len() - 1]);
+            let mut points = Vec::new();
+            for _ in 1..chain.len() {
+                let (from, to) = builder.columns(s);
+                if points.is_empty() {
+                    points.push((boxes[upper].rec.bottom, from));
+                }
+                if from != to {
+                    points.push((tracks[s], from));
+                    points.push((tracks[s], to));
+                }
+                s += 1;
+            }
+            let to = points.last().map_or(0, |&(_, column)| column);
+            points.push((boxes[lower].rec.top, to));
+            // a call against the flow of the layers points up
+            if reversed {
+                points.reverse();
+            }
+            edge_layouts.push(EdgeLayout { points });
+        }
+
+        let width = boxes
+            .iter()
+            .map(|b| b.rec.right + 1)
+            .chain(labels.iter().map(|l| l.right()))
+            .max();
+        let height = boxes
+            .iter()
+            .map(|b| b.rec.bottom + 1)
+            .chain(labels.iter().map(|l| l.bottom()))
+            .max();
+        CollaborationLayout {
+            boxes,
+            edges: edge_layouts,
+            labels,
+            width: width.unwrap_or(0),
+            height: height.unwrap_or(0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum NodeKind {
+    /// The box of a component.
+    Component,
+    /// The label of an edge, in the layer below its upper box.
+    Label,
+    /// A point an edge passes through, in the other layers it spans.
+    Dummy,
+}
+
+/// A node of a layer.
+struct Node {
+    kind: NodeKind,
+    layer: usize,
+    width: usize,
+    height: usize,
+}
+
+struct Builder {
+    nodes: Vec<Node>,
+    /// The parts of the edges between two nodes of adjacent layers, as the
+    /// upper and lower node.
+    segments: Vec<(usize, usize)>,
+    /// The nodes of each layer from left to right.
+    order: Vec<Vec<usize>>,
+    /// Left column of each node, which may be negative while placing them.
+    left: Vec<isize>,
+    /// Columns from the left of its upper and lower node where each segment
+    /// leaves and enters them.
+    ports: Vec<(usize, usize)>,
+}
+
+impl Builder {
+    /// Returns the position in its layer of each node.
+    fn positions(&self) -> Vec<usize> {
+        let mut positions = vec![0; self.nodes.len()];
+        for layer in &self.order {
+            for (position, &n) in layer.iter().enumerate() {
+                positions[n] = position;
+            }
+        }
+        positions
+    }
+
+    /// Returns the number of pairs of segments crossing each other.
+    fn crossings(&self) -> usize {
+        let positions = self.positions();
+        let mut crossings = 0;
+        for (i, &(a, b)) in self.segments.iter().enumerate() {
+            for &(c, d) in &self.segments[i + 1..] {
+                let upper = positions[a] as isize - positions[c] as isize;
+                let lower = positions[b] as isize - positions[d] as isize;
+                if self.nodes[a].layer == self.nodes[c].layer
+                    && upper * lower < 0
+                {
+                    crossings += 1;
+                }
+            }
+        }
+        crossings
+    }
+
+    /// Returns the nodes adjacent to `n` in the layer above if `up`, or
+    /// below, with the segments to them.
+    fn neighbours(&self, n: usize, up: bool) -> Vec<(usize, usize)> {
+        self.segments
+            .iter()
+            .enumerate()
+            .filter_map(|(s, &(a, b))| match up {
+                true if b == n => Some((s, a)),
+                false if a == n => Some((s, b)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Orders the nodes of each layer by the mean position of their
+    /// neighbours in the layer swept from, keeping the order with the
+    /// fewest crossings.
+    fn order_layers(&mut self) {
+        let mut best = (self.crossings(), self.order.clone());
+        for sweep in 0..ORDER_SWEEPS {
+            let down = sweep % 2 == 0;
+            for l in sweep_layers(self.order.len(), down) {
+                let positions = self.positions();
+                let key = |n: usize| {
+                    let neighbours = self.neighbours(n, down);
+                    if neighbours.is_empty() {
+                        return positions[n] as f64;
+                    }
+                    let sum: usize =
+                        neighbours.iter().map(|&(_, m)| positions[m]).sum();
+                    sum as f64 / neighbours.len() as f64
+                };
+                let mut layer = self.order[l].clone();
+                layer.sort_by(|&a, &b| key(a).total_cmp(&key(b)));
+                self.order[l] = layer;
+            }
+            let crossings = self.crossings();
+            if crossings < best.0 {
+                best = (crossings, self.order.clone());
+            }
+        }
+        self.order = best.1;
+    }
+
+    /// Spreads the segments leaving and entering each node over its width,
+    /// two columns apart in the order of the nodes at their other end,
+    /// widening the boxes as needed.
+    fn layout_ports(&mut self) {
+        let positions = self.positions();
+        self.ports = vec![(0, 0); self.segments.len()];
+        for n in 0..self.nodes.len() {
+            let mut above = self.neighbours(n, true);
+            let mut below = self.neighbours(n, false);
+            above.sort_by_key(|&(_, m)| positions[m]);
+            below.sort_by_key(|&(_, m)| positions[m]);
+            let count = max(above.len(), below.len());
+            let node = &mut self.nodes[n];
+            node.width = max(node.width, 2 * count + 1);
+            let center = node.width / 2;
+            for (i, &(s, _)) in above.iter().enumerate() {
+                self.ports[s].1 = center + 2 * i + 1 - above.len();
+            }
+            for (i, &(s, _)) in below.iter().enumerate() {
+                self.ports[s].0 = center + 2 * i + 1 - below.len();
+            }
+        }
+    }
+
+    /// Returns the columns where the segment `s` leaves its upper node and
+    /// enters its lower one.
+    fn columns(&self, s: usize) -> (usize, usize) {
+        let (a, b) = self.segments[s];
+        let (from, to) = self.ports[s];
+        (
+            (self.left[a] + from as isize) as usize,
+            (self.left[b] + to as isize) as usize,
+        )
+    }
+
+    /// Places the nodes of each layer as close as they can be to the
+    /// columns lining their segments up with their neighbours in the layer
+    /// swept from, then moves the leftmost node to column 0.
+    fn place_nodes(&mut self, box_gap: usize) {
+        self.left = vec![0; self.nodes.len()];
+        for layer in 0..self.order.len() {
+            let desired = vec![0; self.order[layer].len()];
+            self.place_layer(layer, &desired, box_gap);
+        }
+        for sweep in 0..PLACE_SWEEPS {
+            let down = sweep % 2 == 0;
+            for l in sweep_layers(self.order.len(), down) {
+                let desired: Vec<isize> = self.order[l]
+                    .iter()
+                    .map(|&n| {
+                        let neighbours = self.neighbours(n, down);
+                        if neighbours.is_empty() {
+                            return self.left[n];
+                        }
+                        let sum: isize = neighbours
+                            .iter()
+                            .map(|&(s, m)| {
+                                let (from, to) = self.ports[s];
+                                let (from, to) = (from as isize, to as isize);
+                                match down {
+                                    true => self.left[m] + from - to,
+                                    false => self.left[m] + to - from,
+                                }
+                            })
+                            .sum();
+                        sum.div_euclid(neighbours.len() as isize)
+                    })
+                    .collect();
+                self.place_layer(l, &desired, box_gap);
+            }
+        }
+        let min_left = self.left.iter().copied().min().unwrap_or(0);
+        for left in &mut self.left {
+            *left -= min_left;
+        }
+    }
+
+    /// Places the nodes of `layer` in order as close as they can be to the
+    /// `desired` columns: the mean of the placements packed from the left
+    /// and from the right, both keeping the gaps.
+    fn place_layer(
+        &mut self,
+        layer: usize,
+        desired: &[isize],
+        box_gap: usize,
+    ) {
+        let nodes = &self.order[layer];
+        let gap = |a: usize, b: usize| {
+            let boxes = self.nodes[a].kind == NodeKind::Component
+                && self.nodes[b].kind == NodeKind::Component;
+            (if boxes { box_gap } else { NODE_GAP }) as isize
+        };
+        let mut from_left = desired.to_vec();
+        for i in 1..nodes.len() {
+            let (a, b) = (nodes[i - 1], nodes[i]);
+            let right = from_left[i - 1] + self.nodes[a].width as isize;
+            from_left[i] = max(from_left[i], right + gap(a, b));
+        }
+        let mut from_right = desired.to_vec();
+        for i in (1..nodes.len()).rev() {
+            let (a, b) = (nodes[i - 1], nodes[i]);
+            let left =
+                from_right[i] - gap(a, b) - self.nodes[a].width as isize;
+            from_right[i - 1] = min(from_right[i - 1], left);
+        }
+        for (i, &n) in nodes.iter().enumerate() {
+            self.left[n] = (from_left[i] + from_right[i]).div_euclid(2);
+        }
+    }
+
+    /// Assigns the segments bent below the nodes of `layer` to the rows of
+    /// tracks from `top`, a segment sharing its track only with the ones it
+    /// does not overlap, and returns the number of tracks.
+    fn layout_tracks(
+        &self,
+        layer: &[usize],
+        top: usize,
+        tracks: &mut [usize],
+    ) -> usize {
+        let bent: Vec<usize> = (0..self.segments.len())
+            .filter(|&s| layer.contains(&self.segments[s].0))
+            .filter(|&s| self.columns(s).0 != self.columns(s).1)
+            .collect();
+        let spans: Vec<(usize, usize)> =
+            bent.iter().map(|&s| self.columns(s)).collect();
+        let (assigned, count) = assign_tracks(&spans);
+        for (&s, track) in bent.iter().zip(assigned) {
+            tracks[s] = top + track;
+        }
+        count
+    }
+}
+
+/// Assigns the horizontal segments of a channel, going from and to the
+/// columns of `spans`, to tracks numbered from the top, a segment sharing
+/// its track only with the ones it does not overlap. Returns the track of
+/// each segment and the number of tracks.
+pub(crate) fn assign_tracks(spans: &[(usize, usize)]) -> (Vec<usize>, usize) {
+    // segments going right are put above the ones they lead to, and the
+    // segments going left below
+    let mut order: Vec<usize> = (0..spans.len()).collect();
+    order.sort_by_key(|&i| {
+        let (from, to) = spans[i];
+        if from < to {
+            (0, usize::MAX - from)
+        } else {
+            (1, from)
+        }
+    });
+    let mut tracks = vec![0; spans.len()];
+    let mut taken: Vec<Vec<(usize, usize)>> = Vec::new();
+    for i in order {
+        let (from, to) = spans[i];
+        let span = (min(from, to), max(from, to));
+        let free = taken.iter().position(|track| {
+            track
+                .iter()
+                .all(|&(left, right)| right + 1 < span.0 || span.1 + 1 < left)
+        });
+        let track = match free {
+            Some(track) => track,
+            None => {
+                taken.push(Vec::new());
+                taken.len() - 1
+            }
+        };
+        taken[track].push(span);
+        tracks[i] = track;
+    }
+    (tracks, taken.len())
+}
+
+/// Returns the layers swept over, out of `count`, by a sweep going `down`
+/// from the second one, or up from the one before the last.
+fn sweep_layers(count: usize, down: bool) -> Vec<usize> {
+    if down {
+        (1..count).collect()
+    } else {
+        (0..count.saturating_sub(1)).rev().collect()
+    }
+}
+
+/// Returns which `edges` between `count` nodes to reverse to break the
+/// cycles: the ones going back to a node on the path of a depth-first
+/// search from the nodes in order.
+fn back_edges(count: usize, edges: &[(usize, usize)]) -> Vec<bool> {
+    #[derive(Clone, Copy, PartialEq)]
+    enum Visit {
+        New,
+        Open,
+        Done,
+    }
+
+    fn visit(
+        n: usize,
+        edges: &[(usize, usize)],
+        visits: &mut [Visit],
+        back: &mut [bool],
+    ) {
+        visits[n] = Visit::Open;
+        for (e, &(a, b)) in edges.iter().enumerate() {
+            if a != n {
+                continue;
+            }
+            match visits[b] {
+                Visit::New => visit(b, edges, visits, back),
+                Visit::Open => back[e] = true,
+                Visit::Done => {}
+            }
+        }
+        visits[n] = Visit::Done;
+    }
+
+    let mut visits = vec![Visit::New; count];
+    let mut back = vec![false; edges.len()];
+    for n in 0..count {
+        if visits[n] == Visit::New {
+            visit(n, edges, &mut visits, &mut back);
+        }
+    }
+    back
+}
+
+/// Returns the rank of each of the `count` nodes of the acyclic graph of
+/// `edges`: the length of the longest path to it.
+fn ranks(count: usize, edges: &[(usize, usize)]) -> Vec<usize> {
+    let mut ranks = vec![0; count];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &(a, b) in edges {
+            if ranks[b] < ranks[a] + 1 {
+                ranks[b] = ranks[a] + 1;
+                changed = true;
+            }
+        }
+    }
+    ranks
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_collaboration_layout() {
+        let txt =
+            fs::read_to_string("./test/callgraph_collaboration.txt").unwrap();
+        let callgraph = CallGraph::new(&txt).unwrap();
+
+        let collaborations = callgraph.collaborations();
+        assert_eq!(collaborations.len(), 6);
+        assert_eq!(
+            collaborations[4],
+            Collaboration {
+                caller: String::from("Payments"),
+                callee: String::from("Bank"),
+                functions: vec![
+                    (String::from("debit"), 1),
+                    (String::from("credit"), 1)
+                ],
+            }
+        );
+
+        let layout =
+            CollaborationLayout::new(&callgraph, &PainterConfig::default());
+        // Client, Bank, Orders, Inventory and Payments, Bank being called
+        // by Payments too
+        let tops: Vec<usize> =
+            layout.boxes.iter().map(|b| b.rec.top).collect();
+        assert_eq!(tops, [0, 30, 9, 20, 20]);
+
+        // the call of Payments back to Orders goes up
+        let upwards: Vec<bool> = layout
+            .edges
+            .iter()
+            .map(|e| e.points[0].0 > e.points[e.points.len() - 1].0)
+            .collect();
+        assert_eq!(upwards, [false, false, false, false, false, true]);
+    }
+}
@@ -0,0 +1,195 @@
+//! This module includes the [`PainterConfig`] type, which holds the drawing
+//! settings of [`Painter`](crate::Painter).
+use crate::callgraph::{Numbering, Style};
+use serde::Deserialize;
+use std::str::FromStr;
+
+/// Kind of diagram the callgraph is drawn as.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Diagram {
+    /// UML sequence diagram, the default.
+    Sequence,
+    /// Indented tree of the calls grouped by component, like the output of
+    /// the `tree` command.
+    Tree,
+    /// Boxes of the components laid out in layers, with arrows from the
+    /// calling components to the called ones.
+    Collaboration,
+    /// UML class-like boxes of the components listing their functions,
+    /// packed in a grid, with arrows from the calling components to the
+    /// called ones.
+    Class,
+    /// Flowchart of the steps of indented text instead of a callgraph,
+    /// with `if` and `else` branches, drawn top-down.
+    Flowchart,
+}
+
+impl FromStr for Diagram {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sequence" => Ok(Diagram::Sequence),
+            "tree" => Ok(Diagram::Tree),
+            "collaboration" => Ok(Diagram::Collaboration),
+            "class" => Ok(Diagram::Class),
+            "flowchart" => Ok(Diagram::Flowchart),
+            _ => Err(format!("unknown diagram `{}`", s)),
+        }
+    }
+}
+
+/// Drawing settings of [`Painter`](crate::Painter), loadable from a TOML
+/// file such as `.ascii_painter.toml`. Missing keys take their default.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PainterConfig {
+    /// Kind of diagram drawn.
+    pub diagram: Diagram,
+    /// Empty rows between two messages.
+    pub vertical_margin: usize,
+    /// Width of the loop drawn for a self-call.
+    pub self_call_width: usize,
+    /// Width beyond which participant labels are wrapped within their box.
+    pub max_participant_width: usize,
+    /// Minimal number of columns between two participant boxes, or the
+    /// branches of a flowchart.
+    pub participant_gap: usize,
+    /// Spaces between a participant box and its label on both sides.
+    pub header_padding: usize,
+    /// Width beyond which message labels, and the texts of flowchart steps,
+    /// are wrapped to multiple lines.
+    pub max_label_width: usize,
+    /// Message numbering, which takes precedence over the `autonumber`
+    /// directive of the callgraph text.
+    pub autonumber: Option<Numbering>,
+    /// Whether each participant box is repeated at the bottom of its
+    /// lifeline.
+    pub footers: bool,
+    /// Repeats the participant boxes after every this many messages.
+    pub repeat_header_every: Option<usize>,
+    /// Columns between tab stops in the indentation of the callgraph text.
+    pub tab_width: usize,
+    /// Separators between the segments of a qualified path in statements,
+    /// such as `::`, `.`, `->`, `#` or `:`.
+    pub separators: Vec<String>,
+    /// Shortens participant labels to the last this many path segments.
+    pub component_segments: Option<usize>,
+    /// Whether the `(args)` of a call are drawn in its message label.
+    pub show_args: bool,
+    /// Whether the `[timing]` of a call is drawn in its message label.
+    pub show_timing: bool,
+    /// Whether the `# annotation` of a call is drawn in its message label.
+    pub show_annotations: bool,
+    /// Characters the diagram is drawn with, which takes precedence over the
+    /// `@style` directive of the callgraph text.
+    pub style: Option<Style>,
+    /// Title above the participant row, which takes precedence over the
+    /// `@title` directive.
+    pub title: Option<String>,
+    /// Caption below the lifelines, which takes precedence over the
+    /// `@caption` directive.
+    pub caption: Option<String>,
+    /// Footer below the caption, which takes precedence over the `@footer`
+    /// directive.
+    pub footer: Option<String>,
+    /// Whether a legend of the arrow styles is drawn below the diagram.
+    pub legend: bool,
+    /// Splits the participants into pages drawn one below the other, each at
+    /// most this many columns wide.
+    pub max_width: Option<usize>,
+    /// Columns on the left of every page but the first, where the messages
+    /// from or to the participants of the previous pages are drawn from.
+    pub page_gutter: usize,
+    /// Splits the messages into pages, each at most this many rows high with
+    /// the participant boxes repeated on top.
+    pub max_height: Option<usize>,
+    /// Folds call subtrees repeated at least this many times in a row into a
+    /// `loop` frame.
+    pub fold_loops: Option<usize>,
+    /// Drops the calls nested deeper than this many calls.
+    pub max_depth: Option<usize>,
+    /// Draws only the participants matching one of these names or globs,
+    /// all if empty.
+    pub include: Vec<String>,
+    /// Leaves out the participants matching one of these names or globs.
+    pub exclude: Vec<String>,
+    /// Leaves out the calls of a participant to itself.
+    pub hide_self_calls: bool,
+    /// Collapses the participants left out into a single `…` participant.
+    pub collapse_excluded: bool,
+    /// Whether the functions of a tree diagram are followed by their number
+    /// of calls.
+    pub call_counts: bool,
+    /// Packs the boxes of a class diagram into a grid at most this many
+    /// columns wide, one row of boxes if unset.
+    pub class_grid_width: Option<usize>,
+}
+
+impl Default for PainterConfig {
+    fn default() -> Self {
+        PainterConfig {
+            diagram: Diagram::Sequence,
+            vertical_margin: 2,
+            self_call_width: 5,
+            max_participant_width: 20,
+            participant_gap: 5,
+            header_padding: 0,
+            max_label_width: 30,
+            autonumber: None,
+            footers: false,
+            repeat_header_every: None,
+            tab_width: 4,
+            separators: vec![String::from("::")],
+            component_segments: None,
+            show_args: true,
+            show_timing: true,
+            show_annotations: false,
+            style: None,
+            title: None,
+            caption: None,
+            footer: None,
+            legend: false,
+            max_width: None,
+            page_gutter: 16,
+            max_height: None,
+            fold_loops: None,
+            max_depth: None,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            hide_self_calls: false,
+            collapse_excluded: false,
+            call_counts: false,
+            class_grid_width: None,
+        }
+    }
+}
+
+impl PainterConfig {
+    /// Parses the config from `toml` text.
+    pub fn from_toml(toml: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(toml)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_from_toml() {
+        let config = PainterConfig::from_toml(
+            "participant_gap = 2\nautonumber = \"hierarchical\"\n\
+             diagram = \"tree\"\n",
+        )
+        .unwrap();
+
+        assert_eq!(config.participant_gap, 2);
+        assert_eq!(config.autonumber, Some(Numbering::Hierarchical));
+        assert_eq!(config.max_label_width, 30);
+        assert_eq!(config.diagram, Diagram::Tree);
+
+        assert!(PainterConfig::from_toml("unknown_key = 1").is_err());
+    }
+}
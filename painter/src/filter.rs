@@ -0,0 +1,295 @@
+//! This module includes the [`Filter`] type, which narrows a [`CallGraph`]
+//! down to the calls and participants of interest.
+use crate::callgraph::{CallGraph, FunctionCall};
+use std::collections::HashSet;
+
+/// Identifier of the participant the excluded ones are collapsed into.
+pub const COLLAPSED: &str = "…";
+
+/// Label of the [`COLLAPSED`] participant in ASCII style.
+pub const COLLAPSED_ASCII: &str = "...";
+
+/// Which calls and participants of a [`CallGraph`] to keep.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Filter {
+    /// Drops the calls nested deeper than this many calls.
+    pub max_depth: Option<usize>,
+    /// Keeps only the participants matching one of these names or globs,
+    /// all if empty.
+    pub include: Vec<String>,
+    /// Drops the participants matching one of these names or globs.
+    pub exclude: Vec<String>,
+    /// Drops the calls of a participant to itself.
+    pub hide_self_calls: bool,
+    /// Collapses the participants left out into a single [`COLLAPSED`] one
+    /// instead of dropping them.
+    pub collapse_excluded: bool,
+}
+
+impl Filter {
+    /// Returns whether the participant `component`, drawn as `label`, is
+    /// kept.
+    pub fn shows(&self, component: &str, label: &str) -> bool {
+        let matches = |patterns: &[String]| {
+            patterns
+                .iter()
+                .any(|p| glob_match(p, component) || glob_match(p, label))
+        };
+        (self.include.is_empty() || matches(&self.include))
+            && !matches(&self.exclude)
+    }
+}
+
+impl CallGraph {
+    /// Drops the calls and participants left out by `filter`. A call from a
+    /// participant left out is re-attributed to the nearest caller up the
+    /// call stack that is kept, or to the outside if there is none. The
+    /// calls are renumbered by their nesting in what is kept.
+    pub fn filter(&mut self, filter: &Filter) {
+        let shown: HashSet<&str> = self
+            .components_in_order
+            .iter()
+            .filter(|c| filter.shows(c, self.label(c)))
+            .map(|c| c.as_str())
+            .collect();
+        let resolve = |component: &str| {
+            if component.is_empty() || shown.contains(component) {
+                Some(component.to_string())
+            } else if filter.collapse_excluded {
+                Some(COLLAPSED.to_string())
+            } else {
+                None
+            }
+        };
+
+        // the calls above the call being filtered, the innermost last
+        let mut ancestors: Vec<&FunctionCall> = Vec::new();
+        // index in the calls kept of each call, and of the end of the calls
+        let mut index = Vec::with_capacity(self.func_calls.len() + 1);
+        let mut calls = Vec::new();
+        for f in &self.func_calls {
+            while ancestors.last().is_some_and(|a| !is_nested(f, a)) {
+                ancestors.pop();
+            }
+            index.push(calls.len());
+            let depth = ancestors.len() + 1;
+            let callers = ancestors.iter().rev().map(|a| a.caller.as_str());
+            let caller = std::iter::once(f.caller.as_str())
+                .chain(callers)
+                .find_map(resolve)
+                .unwrap_or_default();
+            ancestors.push(f);
+
+            let callee = match resolve(&f.callee.0) {
+                Some(callee) => callee,
+                None => continue,
+            };
+            if filter.max_depth.is_some_and(|max_depth| depth > max_depth)
+                || caller == callee
+                    && (filter.hide_self_calls || callee == COLLAPSED)
+            {
+                continue;
+            }
+            calls.push(FunctionCall {
+                caller,
+                callee: (callee, f.callee.1.clone()),
+                ..f.clone()
+            });
+        }
+        index.push(calls.len());
+        renumber(&mut calls);
+
+        let called: HashSet<&str> = calls
+            .iter()
+            .flat_map(|f| [f.caller.as_str(), f.callee.0.as_str()])
+            .collect();
+        let mut components_in_order = Vec::new();
+        for c in &self.components_in_order {
+            let component = if shown.contains(c.as_str()) {
+                c.as_str()
+            } else {
+                COLLAPSED
+            };
+            let declared = self.participants.contains_key(component);
+            if (called.contains(component) || declared)
+                && !components_in_order.iter().any(|k| k == component)
+            {
+                components_in_order.push(component.to_string());
+            }
+        }
+        self.components
+            .retain(|c, _| components_in_order.contains(c));
+        if components_in_order.iter().any(|c| c == COLLAPSED) {
+            let functions = calls
+                .iter()
+                .filter(|f| f.callee.0 == COLLAPSED)
+                .map(|f| f.callee.1.clone())
+                .collect();
+            self.components.insert(COLLAPSED.to_string(), functions);
+        }
+        for (at, _) in &mut self.markers {
+            *at = index[*at];
+        }
+        for group in &mut self.groups {
+            group.components.retain(|c| components_in_order.contains(c));
+        }
+        self.components_in_order = components_in_order;
+        self.func_calls = calls;
+    }
+}
+
+/// Returns whether `f` is nested in the call `ancestor`.
+pub(crate) fn is_nested(f: &FunctionCall, ancestor: &FunctionCall) -> bool {
+    f.number.len() > ancestor.number.len()
+        && f.number.starts_with(&ancestor.number)
+}
+
+/// Numbers `calls` anew by their nesting, each call being nested in the
+/// nearest call before it that it was nested in.
+fn renumber(calls: &mut [FunctionCall]) {
+    // the original and new numbers of the calls above, with the count of
+    // their calls so far, the innermost last
+    let mut stack: Vec<(Vec<usize>, Vec<usize>, usize)> = Vec::new();
+    let mut top_level = 0;
+    for f in calls {
+        while stack.last().is_some_and(|(number, _, _)| {
+            !(f.number.len() > number.len() && f.number.starts_with(number))
+        }) {
+            stack.pop();
+        }
+        let mut number = match stack.last_mut() {
+            Some((_, parent, count)) => {
+                *count += 1;
+                let mut number = parent.clone();
+                number.push(*count);
+                number
+            }
+            None => {
+                top_level += 1;
+                vec![top_level]
+            }
+        };
+        std::mem::swap(&mut f.number, &mut number);
+        stack.push((number, f.number.clone(), 0));
+    }
+}
+
+/// Returns whether `text` matches `pattern`, where `*` matches any
+/// characters and `?` any single one.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // the last `*` and the text position it matches up to
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    star = Some((star_p, star_t + 1));
+                    p = star_p + 1;
+                    t = star_t + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const TXT: &str = "\
+Client::checkout
+  Orders::place_order
+    OrderRepo::save
+      Orders::on_saved
+    Orders::validate
+  Payments::charge
+";
+
+    fn calls(callgraph: &CallGraph) -> Vec<(&str, &str, &[usize])> {
+        callgraph
+            .func_calls
+            .iter()
+            .map(|f| {
+                (f.caller.as_str(), f.callee.0.as_str(), f.number.as_slice())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*Repo", "OrderRepo"));
+        assert!(glob_match("Order?", "Orders"));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("Order?", "OrderRepo"));
+        assert!(!glob_match("*Repo", "Orders"));
+    }
+
+    #[test]
+    fn test_filter() {
+        let mut callgraph = CallGraph::new(TXT).unwrap();
+        callgraph.filter(&Filter {
+            exclude: vec![String::from("*Repo")],
+            ..Default::default()
+        });
+        assert_eq!(
+            calls(&callgraph),
+            [
+                ("", "Client", &[1][..]),
+                ("Client", "Orders", &[1, 1]),
+                ("Orders", "Orders", &[1, 1, 1]),
+                ("Orders", "Orders", &[1, 1, 2]),
+                ("Client", "Payments", &[1, 2]),
+            ]
+        );
+        assert_eq!(
+            callgraph.components_in_order,
+            ["Client", "Orders", "Payments"]
+        );
+
+        let mut callgraph = CallGraph::new(TXT).unwrap();
+        callgraph.filter(&Filter {
+            include: vec![String::from("Client"), String::from("Orders")],
+            hide_self_calls: true,
+            collapse_excluded: true,
+            ..Default::default()
+        });
+        assert_eq!(
+            calls(&callgraph),
+            [
+                ("", "Client", &[1][..]),
+                ("Client", "Orders", &[1, 1]),
+                ("Orders", COLLAPSED, &[1, 1, 1]),
+                (COLLAPSED, "Orders", &[1, 1, 1, 1]),
+                ("Client", COLLAPSED, &[1, 2]),
+            ]
+        );
+        assert_eq!(
+            callgraph.components_in_order,
+            ["Client", "Orders", COLLAPSED]
+        );
+
+        let mut callgraph = CallGraph::new(TXT).unwrap();
+        callgraph.filter(&Filter {
+            max_depth: Some(2),
+            ..Default::default()
+        });
+        assert_eq!(callgraph.func_calls.len(), 3);
+        assert_eq!(
+            callgraph.components_in_order,
+            ["Client", "Orders", "Payments"]
+        );
+    }
+}
@@ -0,0 +1,674 @@
+//! This module includes the [`Flowchart`] type, which holds the steps of a
+//! flowchart parsed from indented text, and the [`FlowchartLayout`] type,
+//! which holds the geometry of its diagram drawn top-down.
+use crate::callgraph::{
+    indent_width, strip_keyword, ParseError, ParseOptions, Style,
+};
+use crate::collaboration::EdgeLayout;
+use crate::config::PainterConfig;
+use crate::layout::{wrap, TextLayout};
+use canvas::Rectangle;
+use std::cmp::max;
+
+/// A step of a [`Flowchart`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Step {
+    /// An action, drawn as a box.
+    Process(String),
+    /// A `start` or an `end` of the flow, drawn as a rounded box. The flow
+    /// does not go on after an `end`.
+    Terminal { text: String, end: bool },
+    /// An `if` with the steps of its branches: the `yes` one indented below
+    /// it, and the `no` one indented below the `else` following it.
+    Decision {
+        condition: String,
+        yes: Vec<Step>,
+        no: Vec<Step>,
+    },
+}
+
+/// The steps of a flowchart, parsed from text with a step per line, e.g.
+///
+/// ```text
+/// start
+/// Read the order
+/// if In stock?
+///     Reserve the items
+/// else
+///     Back-order the items
+/// Send a confirmation
+/// end
+/// ```
+///
+/// A `@style ascii` or `@style unicode` line sets the style of the diagram.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Flowchart {
+    pub steps: Vec<Step>,
+    pub style: Option<Style>,
+}
+
+impl Flowchart {
+    /// Processes `flowchart` text and generates [`Flowchart`], or the
+    /// [`ParseError`]s of all the lines that cannot be understood.
+    pub fn new(flowchart: &str) -> Result<Self, Vec<ParseError>> {
+        Self::with_options(flowchart, &ParseOptions::default())
+    }
+
+    /// Same as [`Flowchart::new`], with the indentation measured by the
+    /// `tab_width` of `options`.
+    pub fn with_options(
+        flowchart: &str,
+        options: &ParseOptions,
+    ) -> Result<Self, Vec<ParseError>> {
+        let mut lines = Vec::new();
+        let mut style = None;
+        let mut errors = Vec::new();
+        for (index, text) in flowchart.split('\n').enumerate() {
+            let text = text.strip_suffix('\r').unwrap_or(text);
+            let trimmed = text.trim();
+            if trimmed.is_empty()
+                || trimmed.starts_with('#')
+                || trimmed.starts_with("//")
+            {
+                continue;
+            }
+            let offset = text.len() - text.trim_start().len();
+            if let Some(directive) = trimmed.strip_prefix('@') {
+                let name = directive.split_whitespace().next().unwrap_or("");
+                let parsed = match strip_keyword(directive, "style") {
+                    Some(value) => value.parse().map(|s| style = Some(s)),
+                    None => Err(format!("unknown directive `@{}`", name)),
+                };
+                if let Err(message) = parsed {
+                    let len = trimmed.len();
+                    errors.push(ParseError::new(
+                        index, text, offset, len, message,
+                    ));
+                }
+                continue;
+            }
+            lines.push(Line {
+                index,
+                text,
+                offset,
+                depth: indent_width(&text[..offset], options.tab_width),
+            });
+        }
+
+        let mut parser = Parser {
+            lines,
+            next: 0,
+            errors: Vec::new(),
+        };
+        let steps = parser.block(0);
+        errors.extend(parser.errors);
+        if errors.is_empty() {
+            Ok(Flowchart { steps, style })
+        } else {
+            errors.sort_by_key(|e| e.line);
+            Err(errors)
+        }
+    }
+}
+
+/// A non-blank line of flowchart text.
+#[derive(Clone, Copy)]
+struct Line<'a> {
+    /// 0-based line number.
+    index: usize,
+    text: &'a str,
+    /// Byte offset of the statement, after the indentation.
+    offset: usize,
+    /// Width of the indentation.
+    depth: usize,
+}
+
+impl<'a> Line<'a> {
+    fn statement(&self) -> &'a str {
+        self.text[self.offset..].trim_end()
+    }
+
+    /// Returns the error spanning the whole statement.
+    fn error(&self, message: &str) -> ParseError {
+        let len = self.statement().len();
+        ParseError::new(
+            self.index,
+            self.text,
+            self.offset,
+            len,
+            message.to_string(),
+        )
+    }
+}
+
+/// Intermediate state of parsing a [`Flowchart`].
+struct Parser<'a> {
+    lines: Vec<Line<'a>>,
+    /// Index in `lines` of the next line to parse.
+    next: usize,
+    errors: Vec<ParseError>,
+}
+
+impl<'a> Parser<'a> {
+    /// Parses the steps indented by `depth`, up to the first line indented
+    /// less.
+    fn block(&mut self, depth: usize) -> Vec<Step> {
+        let mut steps = Vec::new();
+        while let Some(&line) = self.lines.get(self.next) {
+            if line.depth < depth {
+                break;
+            }
+            if line.depth > depth {
+                self.errors.push(line.error("unexpected indent"));
+                // the steps below are still parsed for their errors
+                self.block(line.depth);
+                continue;
+            }
+            self.next += 1;
+            let statement = line.statement();
+            if let Some(condition) = strip_keyword(statement, "if") {
+                if condition.is_empty() {
+                    self.errors.push(line.error("missing condition of `if`"));
+                }
+                let yes = self.branch(depth);
+                let no = match self.lines.get(self.next) {
+                    Some(&line) if line.depth == depth => {
+                        match strip_keyword(line.statement(), "else") {
+                            Some(rest) => {
+                                if !rest.is_empty() {
+                                    self.errors.push(line.error(
+                                        "unexpected text after `else`",
+                                    ));
+                                }
+                                self.next += 1;
+                                self.branch(depth)
+                            }
+                            None => Vec::new(),
+                        }
+                    }
+                    _ => Vec::new(),
+                };
+                steps.push(Step::Decision {
+                    condition: condition.to_string(),
+                    yes,
+                    no,
+                });
+            } else if strip_keyword(statement, "else").is_some() {
+                self.errors.push(line.error("`else` without `if`"));
+                self.branch(depth);
+            } else if let Some(text) = strip_keyword(statement, "start") {
+                steps.push(terminal(text, "Start", false));
+            } else if let Some(text) = strip_keyword(statement, "end") {
+                steps.push(terminal(text, "End", true));
+            } else {
+                steps.push(Step::Process(statement.to_string()));
+            }
+        }
+        steps
+    }
+
+    /// Parses the steps indented below the last line parsed, indented by
+    /// `depth`.
+    fn branch(&mut self, depth: usize) -> Vec<Step> {
+        match self.lines.get(self.next) {
+            Some(&line) if line.depth > depth => self.block(line.depth),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Returns the terminal step with `text`, or `default` if empty.
+fn terminal(text: &str, default: &str, end: bool) -> Step {
+    let text = if text.is_empty() { default } else { text };
+    Step::Terminal {
+        text: text.to_string(),
+        end,
+    }
+}
+
+/// Shape of the box of a step.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shape {
+    /// A plain box.
+    Process,
+    /// A box with `◇` on both sides, its `yes` branch going on below it and
+    /// its `no` branch leaving from its right.
+    Decision,
+    /// A box with rounded corners.
+    Terminal,
+}
+
+/// The box of a step with its text.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeLayout {
+    pub shape: Shape,
+    pub rec: Rectangle,
+    pub label: TextLayout,
+}
+
+/// Geometry of the diagram of a [`Flowchart`], to be drawn by a
+/// [`FlowchartRenderer`](crate::render::FlowchartRenderer).
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlowchartLayout {
+    /// Boxes of the steps, in the order of the text.
+    pub nodes: Vec<NodeLayout>,
+    /// Arrows from each step to the next ones.
+    pub edges: Vec<EdgeLayout>,
+    /// Lines from the ends of the branches of a decision to the point where
+    /// they merge into the arrow going on below the decision.
+    pub merges: Vec<EdgeLayout>,
+    /// `yes` and `no` labels of the branches of the decisions, next to the
+    /// arrows they leave by.
+    pub labels: Vec<TextLayout>,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl FlowchartLayout {
+    /// Lays out the steps of `flowchart` top-down along a vertical axis. The
+    /// `yes` branch of a decision goes on along the axis and its `no` branch
+    /// along another axis to the right of it, both merging back below the
+    /// longer one.
+    pub fn new(flowchart: &Flowchart, config: &PainterConfig) -> Self {
+        let mut builder = Builder {
+            config,
+            layout: FlowchartLayout {
+                nodes: Vec::new(),
+                edges: Vec::new(),
+                merges: Vec::new(),
+                labels: Vec::new(),
+                width: 0,
+                height: 0,
+            },
+            labels: Vec::new(),
+        };
+        let (left, right) = builder.extent(&flowchart.steps);
+        let (_, bottom) = builder.block(&flowchart.steps, left, 0, None);
+
+        let mut layout = builder.layout;
+        if !layout.nodes.is_empty() {
+            layout.width = left + right + 1;
+            layout.height = layout
+                .labels
+                .iter()
+                .map(|l| l.bottom())
+                .fold(bottom + 1, max);
+        }
+        layout
+    }
+}
+
+/// The arrow leaving a step to the next one, as its points up to there.
+type Exit = Vec<(usize, usize)>;
+
+/// Intermediate state of laying out a [`FlowchartLayout`].
+struct Builder<'a> {
+    config: &'a PainterConfig,
+    layout: FlowchartLayout,
+    /// Labels of the branches, with the first point of their arrow, until
+    /// the arrow is drawn.
+    labels: Vec<((usize, usize), TextLayout)>,
+}
+
+impl<'a> Builder<'a> {
+    /// Returns the text of a box wrapped to lines, and the width of the box.
+    fn wrap(&self, text: &str) -> (Vec<String>, usize) {
+        let lines = wrap(text, self.config.max_label_width);
+        let width = lines.iter().map(|l| l.chars().count()).max();
+        (lines, width.unwrap_or(0) + 4)
+    }
+
+    /// Returns the columns taken by `steps` left and right of their axis.
+    fn extent(&self, steps: &[Step]) -> (usize, usize) {
+        steps.iter().fold((0, 0), |(left, right), step| {
+            let (l, r) = match step {
+                Step::Process(text) | Step::Terminal { text, .. } => {
+                    let (_, width) = self.wrap(text);
+                    (width / 2, width - 1 - width / 2)
+                }
+                Step::Decision { condition, yes, no } => {
+                    let (_, width) = self.wrap(condition);
+                    let (yes_left, yes_right) = self.extent(yes);
+                    let (no_left, no_right) = self.extent(no);
+                    let offset = self.no_offset(
+                        width - 1 - width / 2,
+                        yes_right,
+                        no_left,
+                    );
+                    (max(width / 2, yes_left), offset + no_right)
+                }
+            };
+            (max(left, l), max(right, r))
+        })
+    }
+
+    /// Returns the columns between the axis of a decision and the one of
+    /// its `no` branch, clear of the decision box, which takes `right`
+    /// columns right of the axis, and of the `yes` branch.
+    fn no_offset(
+        &self,
+        right: usize,
+        yes_right: usize,
+        no_left: usize,
+    ) -> usize {
+        // room for the `no` label above the arrow
+        max(
+            right + 5,
+            yes_right + 1 + self.config.participant_gap + no_left,
+        )
+    }
+
+    /// Lays out `steps` along `axis`, the first one from row `top`, with
+    /// the arrow of `entry` leading to it. Returns the arrows leaving the
+    /// last step, and the last row taken.
+    fn block(
+        &mut self,
+        steps: &[Step],
+        axis: usize,
+        top: usize,
+        entry: Option<Exit>,
+    ) -> (Vec<Exit>, usize) {
+        let mut exits: Vec<Exit> = entry.into_iter().collect();
+        let mut top = top;
+        let mut bottom = top;
+        for (i, step) in steps.iter().enumerate() {
+            if i > 0 {
+                top = self.merge(&mut exits, axis, bottom);
+            }
+            for mut exit in exits.drain(..) {
+                exit.push((top, axis));
+                let edge = self.arrow(exit);
+                self.layout.edges.push(edge);
+            }
+            let (step_exits, step_bottom) = self.step(step, axis, top);
+            exits = step_exits;
+            bottom = step_bottom;
+        }
+        (exits, bottom)
+    }
+
+    /// Merges the arrows of `exits`, leaving steps that end by row
+    /// `bottom`, into one going on along `axis`. The ones away from the axis
+    /// go down and left to it, each on its own row, from the leftmost.
+    /// Returns the top row of the next step.
+    fn merge(
+        &mut self,
+        exits: &mut Vec<Exit>,
+        axis: usize,
+        bottom: usize,
+    ) -> usize {
+        exits.sort_by_key(|exit| exit.last().map(|&(_, column)| column));
+        let mut row = bottom + 2;
+        let mut others = exits.split_off(exits.len().min(1));
+        if let Some(main) = exits.first_mut() {
+            let &(_, column) = main.last().unwrap();
+            if column != axis {
+                main.push((row, column));
+                main.push((row, axis));
+                row += 1;
+            }
+        }
+        for mut other in others.drain(..) {
+            let &(_, column) = other.last().unwrap();
+            other.push((row, column));
+            other.push((row, axis));
+            let merge = self.arrow(other);
+            self.layout.merges.push(merge);
+            row += 1;
+        }
+        if row == bottom + 2 {
+            bottom + 3
+        } else {
+            row + 1
+        }
+    }
+
+    /// Lays out `step` along `axis` from row `top`. Returns the arrows
+    /// leaving it, and the last row taken.
+    fn step(
+        &mut self,
+        step: &Step,
+        axis: usize,
+        top: usize,
+    ) -> (Vec<Exit>, usize) {
+        match step {
+            Step::Process(text) => {
+                let rec = self.node(Shape::Process, text, axis, top);
+                (vec![vec![(rec.bottom, axis)]], rec.bottom)
+            }
+            Step::Terminal { text, end } => {
+                let rec = self.node(Shape::Terminal, text, axis, top);
+                let exits = if *end {
+                    Vec::new()
+                } else {
+                    vec![vec![(rec.bottom, axis)]]
+                };
+                (exits, rec.bottom)
+            }
+            Step::Decision { condition, yes, no } => {
+                let rec = self.node(Shape::Decision, condition, axis, top);
+                let middle = (rec.top + rec.bottom) / 2;
+                let (_, yes_right) = self.extent(yes);
+                let (no_left, _) = self.extent(no);
+                let no_axis = axis
+                    + self.no_offset(rec.right - axis, yes_right, no_left);
+                self.label(
+                    (rec.bottom, axis),
+                    rec.bottom + 1,
+                    axis + 2,
+                    "yes",
+                );
+                self.label(
+                    (middle, rec.right),
+                    middle - 1,
+                    rec.right + 2,
+                    "no",
+                );
+
+                let mut exits = Vec::new();
+                let mut bottom = rec.bottom;
+                let branches = [
+                    (yes, axis, vec![(rec.bottom, axis)]),
+                    (
+                        no,
+                        no_axis,
+                        vec![(middle, rec.right), (middle, no_axis)],
+                    ),
+                ];
+                for (steps, axis, entry) in branches.iter().cloned() {
+                    if steps.is_empty() {
+                        exits.push(entry);
+                    } else {
+                        let (branch_exits, branch_bottom) = self.block(
+                            steps,
+                            axis,
+                            rec.bottom + 3,
+                            Some(entry),
+                        );
+                        exits.extend(branch_exits);
+                        bottom = max(bottom, branch_bottom);
+                    }
+                }
+                (exits, bottom)
+            }
+        }
+    }
+
+    /// Adds the box of `shape` with `text`, centered on `axis` from row
+    /// `top`, and returns its rectangle.
+    fn node(
+        &mut self,
+        shape: Shape,
+        text: &str,
+        axis: usize,
+        top: usize,
+    ) -> Rectangle {
+        let (lines, width) = self.wrap(text);
+        let left = axis - width / 2;
+        let rec = Rectangle {
+            left,
+            right: left + width - 1,
+            top,
+            bottom: top + lines.len() + 1,
+        };
+        self.layout.nodes.push(NodeLayout {
+            shape,
+            rec: rec.clone(),
+            label: TextLayout {
+                top: top + 1,
+                left: left + 2,
+                lines,
+            },
+        });
+        rec
+    }
+
+    /// Adds the label `text` at row `top` and column `left` once the arrow
+    /// from `from` is drawn, so that a branch going nowhere is not labeled.
+    fn label(
+        &mut self,
+        from: (usize, usize),
+        top: usize,
+        left: usize,
+        text: &str,
+    ) {
+        let label = TextLayout {
+            top,
+            left,
+            lines: vec![text.to_string()],
+        };
+        self.labels.push((from, label));
+    }
+
+    /// Returns the arrow through `points`, adding the labels of the branch
+    /// it leaves by.
+    fn arrow(&mut self, points: Exit) -> EdgeLayout {
+        let (labels, pending): (Vec<_>, Vec<_>) = self
+            .labels
+            .drain(..)
+            .partition(|(from, _)| points.first() == Some(from));
+        self.labels = pending;
+        self.layout
+            .labels
+            .extend(labels.into_iter().map(|(_, label)| label));
+        EdgeLayout { points }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn process(text: &str) -> Step {
+        Step::Process(text.to_string())
+    }
+
+    #[test]
+    fn test_parse() {
+        let txt = "\
+@style ascii
+start
+Read the order
+if In stock?
+    Reserve the items
+    if Paid?
+        Ship
+else
+    // comments and blank lines are skipped
+
+    Back-order the items
+    end Cancelled
+end
+";
+        let flowchart = Flowchart::new(txt).unwrap();
+        assert_eq!(
+            flowchart.steps,
+            [
+                terminal("", "Start", false),
+                process("Read the order"),
+                Step::Decision {
+                    condition: String::from("In stock?"),
+                    yes: vec![
+                        process("Reserve the items"),
+                        Step::Decision {
+                            condition: String::from("Paid?"),
+                            yes: vec![process("Ship")],
+                            no: Vec::new(),
+                        },
+                    ],
+                    no: vec![
+                        process("Back-order the items"),
+                        terminal("Cancelled", "End", true),
+                    ],
+                },
+                terminal("", "End", true),
+            ]
+        );
+        assert_eq!(flowchart.style, Some(Style::Ascii));
+
+        let errors = Flowchart::new(
+            "if\n    A\n  B\nelse\nif C\nelse D\n@style bold\n@color red\n",
+        )
+        .unwrap_err();
+        let errors: Vec<(usize, &str)> = errors
+            .iter()
+            .map(|e| (e.line, e.message.as_str()))
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                (1, "missing condition of `if`"),
+                (3, "unexpected indent"),
+                (4, "`else` without `if`"),
+                (6, "unexpected text after `else`"),
+                (7, "unknown style `bold`"),
+                (8, "unknown directive `@color`"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_layout() {
+        let txt = "\
+A
+if B?
+    C
+else
+    D
+    E
+F
+";
+        let flowchart = Flowchart::new(txt).unwrap();
+        let layout =
+            FlowchartLayout::new(&flowchart, &PainterConfig::default());
+
+        let tops: Vec<usize> =
+            layout.nodes.iter().map(|n| n.rec.top).collect();
+        assert_eq!(tops, [0, 5, 10, 10, 15, 21]);
+        let axes: Vec<usize> = layout
+            .nodes
+            .iter()
+            .map(|n| (n.rec.left + n.rec.right).div_ceil(2))
+            .collect();
+        assert_eq!(axes, [3, 3, 3, 13, 13, 3]);
+        assert_eq!(layout.nodes[1].shape, Shape::Decision);
+
+        // the `no` branch merges into the arrow going on from the `yes` one
+        assert_eq!(
+            layout.merges,
+            [EdgeLayout {
+                points: vec![(17, 13), (19, 13), (19, 3)],
+            }]
+        );
+        assert_eq!(layout.edges.last().unwrap().points, [(12, 3), (21, 3)]);
+        assert_eq!((layout.width, layout.height), (16, 24));
+
+        // the branches of the last decision go nowhere, so are not labeled
+        let flowchart = Flowchart::new("A\nif B?\nelse\n    C\n").unwrap();
+        let layout =
+            FlowchartLayout::new(&flowchart, &PainterConfig::default());
+        let labels: Vec<&str> =
+            layout.labels.iter().map(|l| l.lines[0].as_str()).collect();
+        assert_eq!(labels, ["no"]);
+    }
+}
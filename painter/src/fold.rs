@@ -0,0 +1,246 @@
+//! This module folds the calls of a [`CallGraph`] repeated in a row into
+//! [`Loop`]s.
+use crate::callgraph::{CallGraph, FunctionCall, Loop};
+use std::ops::Range;
+
+impl CallGraph {
+    /// Folds each run of call subtrees repeated at least `min_count` times in
+    /// a row into its first instance, recorded in [`CallGraph::loops`]. A
+    /// run may repeat a sequence of several sibling subtrees, and is not
+    /// folded across a divider or a delay. Calls compare equal regardless of
+    /// their timing and annotation, which are kept from the first instance.
+    pub fn fold_loops(&mut self, min_count: usize) {
+        let min_count = min_count.max(2);
+        let mut kept = vec![true; self.func_calls.len()];
+        let mut loops = Vec::new();
+        self.fold_siblings(
+            0..self.func_calls.len(),
+            min_count,
+            &mut kept,
+            &mut loops,
+        );
+        if kept.iter().all(|&k| k) {
+            return;
+        }
+
+        // new index of each call, and of the end of the calls
+        let mut index = Vec::with_capacity(kept.len() + 1);
+        let mut count = 0;
+        for &k in &kept {
+            index.push(count);
+            count += k as usize;
+        }
+        index.push(count);
+
+        let removed: Vec<Vec<usize>> = self
+            .func_calls
+            .iter()
+            .zip(&kept)
+            .filter(|(_, &k)| !k)
+            .map(|(f, _)| f.number.clone())
+            .collect();
+        let calls = std::mem::take(&mut self.func_calls);
+        self.func_calls = calls
+            .into_iter()
+            .zip(&kept)
+            .filter(|(_, &k)| k)
+            .map(|(mut f, _)| {
+                f.number = renumbered(&f.number, &removed);
+                f
+            })
+            .collect();
+        for (at, _) in &mut self.markers {
+            *at = index[*at];
+        }
+        self.loops = loops
+            .into_iter()
+            .map(|l| Loop {
+                calls: index[l.calls.start]..index[l.calls.end],
+                count: l.count,
+            })
+            .collect();
+    }
+
+    /// Splits `calls` into the ranges of its sibling subtrees.
+    fn subtrees(&self, calls: Range<usize>) -> Vec<Range<usize>> {
+        let mut trees = Vec::new();
+        let mut i = calls.start;
+        while i < calls.end {
+            let root = &self.func_calls[i].number;
+            let mut end = i + 1;
+            while end < calls.end
+                && self.func_calls[end].number.len() > root.len()
+                && self.func_calls[end].number.starts_with(root)
+            {
+                end += 1;
+            }
+            trees.push(i..end);
+            i = end;
+        }
+        trees
+    }
+
+    /// Folds the runs of the sibling subtrees of `calls`, then the runs
+    /// within each subtree left, marking the calls folded away in `kept`.
+    fn fold_siblings(
+        &self,
+        calls: Range<usize>,
+        min_count: usize,
+        kept: &mut [bool],
+        loops: &mut Vec<Loop>,
+    ) {
+        let trees = self.subtrees(calls);
+        let mut i = 0;
+        while i < trees.len() {
+            // the run of blocks of `len` subtrees covering the most calls
+            let mut best: Option<(usize, usize)> = None;
+            for len in 1..=(trees.len() - i) / min_count {
+                let mut count = 1;
+                let block = |k: usize| {
+                    trees[i + k * len].start..trees[i + (k + 1) * len - 1].end
+                };
+                while i + (count + 1) * len <= trees.len()
+                    && self.repeats(block(0), block(count))
+                {
+                    count += 1;
+                }
+                let covered = |(len, count): (usize, usize)| {
+                    trees[i + len * count - 1].end
+                };
+                if count >= min_count
+                    && best.is_none_or(|b| covered((len, count)) > covered(b))
+                {
+                    best = Some((len, count));
+                }
+            }
+            let (len, count) = best.unwrap_or((1, 1));
+            if count > 1 {
+                let run = trees[i].start..trees[i + len * count - 1].end;
+                let end = trees[i + len - 1].end;
+                loops.push(Loop {
+                    calls: trees[i].start..end,
+                    count,
+                });
+                for k in &mut kept[end..run.end] {
+                    *k = false;
+                }
+            }
+            for tree in &trees[i..i + len] {
+                self.fold_siblings(
+                    tree.start + 1..tree.end,
+                    min_count,
+                    kept,
+                    loops,
+                );
+            }
+            i += len * count;
+        }
+    }
+
+    /// Returns whether the subtrees of `calls` repeat the ones of `block`
+    /// right after it, with no marker in between.
+    fn repeats(&self, block: Range<usize>, calls: Range<usize>) -> bool {
+        let depth = |i: usize, root: usize| {
+            self.func_calls[i].number.len()
+                - self.func_calls[root].number.len()
+        };
+        calls.len() == block.len()
+            && !self
+                .markers
+                .iter()
+                .any(|(at, _)| block.start < *at && *at < calls.end)
+            && block.clone().zip(calls.clone()).all(|(a, b)| {
+                same_call(&self.func_calls[a], &self.func_calls[b])
+                    && depth(a, block.start) == depth(b, calls.start)
+            })
+    }
+}
+
+/// Returns whether `a` and `b` call the same function in the same way.
+fn same_call(a: &FunctionCall, b: &FunctionCall) -> bool {
+    a.caller == b.caller
+        && a.callee == b.callee
+        && a.kind == b.kind
+        && a.args == b.args
+}
+
+/// Returns `number` with the numbers of the `removed` calls before it on
+/// each of its levels taken out.
+fn renumbered(number: &[usize], removed: &[Vec<usize>]) -> Vec<usize> {
+    (0..number.len())
+        .map(|level| {
+            let before = removed.iter().filter(|r| {
+                r.len() == level + 1
+                    && r[..level] == number[..level]
+                    && r[level] < number[level]
+            });
+            number[level] - before.count()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_fold_loops() {
+        let txt = "\
+ClassA::run
+  ClassB::poll
+    ClassC::read [1ms]
+  ClassB::poll
+    ClassC::read [2ms]
+  ClassB::poll
+    ClassC::read [1ms]
+  ClassB::open
+  ClassB::send
+  ClassB::open
+  ClassB::send
+  ClassB::close
+";
+        let mut callgraph = CallGraph::new(txt).unwrap();
+        callgraph.fold_loops(2);
+
+        let calls: Vec<(&str, &[usize])> = callgraph
+            .func_calls
+            .iter()
+            .map(|f| (f.callee.1.as_str(), f.number.as_slice()))
+            .collect();
+        assert_eq!(
+            calls,
+            [
+                ("run", &[1][..]),
+                ("poll", &[1, 1]),
+                ("read", &[1, 1, 1]),
+                ("open", &[1, 2]),
+                ("send", &[1, 3]),
+                ("close", &[1, 4]),
+            ]
+        );
+        assert_eq!(
+            callgraph.loops,
+            [
+                Loop {
+                    calls: 1..3,
+                    count: 3
+                },
+                Loop {
+                    calls: 3..5,
+                    count: 2
+                }
+            ]
+        );
+
+        let mut callgraph = CallGraph::new(txt).unwrap();
+        callgraph.fold_loops(3);
+        assert_eq!(callgraph.func_calls.len(), 8);
+        assert_eq!(
+            callgraph.loops,
+            [Loop {
+                calls: 1..3,
+                count: 3
+            }]
+        );
+    }
+}
@@ -0,0 +1,1124 @@
+//! This module includes the [`SequenceLayout`] type, which holds the geometry
+//! of the UML sequence diagram of a [`CallGraph`] as plain data, to be drawn
+//! by a [`Renderer`](crate::render::Renderer).
+use crate::callgraph::{
+    CallGraph, FunctionCall, Loop, Marker, MessageKind, Numbering,
+};
+use crate::config::PainterConfig;
+use canvas::Rectangle;
+use std::cmp::{max, min};
+use std::collections::{HashMap, HashSet};
+
+/// Text wrapped to lines, written from its top left cell.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextLayout {
+    pub top: usize,
+    pub left: usize,
+    pub lines: Vec<String>,
+}
+
+impl TextLayout {
+    fn new(top: usize, left: usize, text: &str, width: usize) -> Self {
+        TextLayout {
+            top,
+            left,
+            lines: wrap(text, width),
+        }
+    }
+
+    /// Returns the column right after the longest line.
+    pub(crate) fn right(&self) -> usize {
+        let width = self.lines.iter().map(|l| l.chars().count()).max();
+        self.left + width.unwrap_or(0)
+    }
+
+    /// Returns the row right after the last line.
+    pub(crate) fn bottom(&self) -> usize {
+        self.top + self.lines.len()
+    }
+}
+
+/// A box with its label.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BoxLayout {
+    pub rec: Rectangle,
+    pub label: TextLayout,
+}
+
+/// Style of the arrowhead of a message.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArrowHead {
+    Filled,
+    Open,
+}
+
+/// A participant: its boxes and its lifeline.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParticipantLayout {
+    /// Component identifier.
+    pub component: String,
+    /// Label drawn in the boxes.
+    pub label: String,
+    /// Column of the lifeline.
+    pub center: usize,
+    /// Boxes from top to bottom: the header, then the repeated ones.
+    pub boxes: Vec<BoxLayout>,
+    /// Segments of the lifeline between the boxes and around the labels of
+    /// the messages passing over it, as exclusive `(start, end)` rows.
+    pub lifeline: Vec<(usize, usize)>,
+    /// Row of the `X` ending the lifeline, if destroyed.
+    pub destroyed: Option<usize>,
+    /// Segments of the lifeline broken by delays, as exclusive
+    /// `(start, end)` rows.
+    pub broken: Vec<(usize, usize)>,
+    /// Rows of the `⋮` marking the lifeline continued across a page break.
+    pub continued: Vec<usize>,
+}
+
+/// A message between two participants, or from outside at column 0.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MessageLayout {
+    pub row: usize,
+    /// Column the arrow starts from.
+    pub from: usize,
+    /// Column of the arrowhead.
+    pub to: usize,
+    /// Indices in participants of the caller and the callee, `None` for
+    /// outside the diagram or its page.
+    pub endpoints: (Option<usize>, Option<usize>),
+    pub head: ArrowHead,
+    pub label: TextLayout,
+}
+
+/// A self-call loop on one side of a lifeline, labeled on its outer side.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelfCallLayout {
+    /// Column of the lifeline.
+    pub column: usize,
+    /// Column of the vertical side of the loop, left of `column` for a loop
+    /// on the left.
+    pub right: usize,
+    pub top: usize,
+    pub bottom: usize,
+    pub head: ArrowHead,
+    pub label: TextLayout,
+}
+
+impl SelfCallLayout {
+    /// Returns the columns the loop and its label span, with the right one
+    /// exclusive.
+    pub(crate) fn columns(&self) -> (usize, usize) {
+        let left = min(min(self.column, self.right), self.label.left);
+        (
+            left,
+            max(max(self.column, self.right) + 1, self.label.right()),
+        )
+    }
+}
+
+/// A labeled frame around a part of the diagram.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameLayout {
+    pub rec: Rectangle,
+    pub label: TextLayout,
+}
+
+/// A named band across the whole diagram between two sections.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DividerLayout {
+    pub row: usize,
+    /// Columns the band spans, with `right` exclusive.
+    pub left: usize,
+    pub right: usize,
+    pub label: TextLayout,
+}
+
+/// A gap of elapsed time, over which the lifelines are broken.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DelayLayout {
+    /// Exclusive `(start, end)` rows of the gap.
+    pub rows: (usize, usize),
+    pub label: Option<TextLayout>,
+}
+
+/// A sample arrow of a message kind in the legend, labeled with its meaning
+/// on the right.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LegendEntry {
+    /// Kind of message the arrow stands for, whose marker, such as the box
+    /// of a creation or the `X` of a destruction, ends the arrow.
+    pub kind: MessageKind,
+    pub message: MessageLayout,
+}
+
+/// A box explaining the arrow styles used in the diagram.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LegendLayout {
+    pub rec: Rectangle,
+    /// A sample arrow of each style.
+    pub entries: Vec<LegendEntry>,
+}
+
+/// Geometry of a UML sequence diagram.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SequenceLayout {
+    /// Number of columns the diagram spans from column 0.
+    pub width: usize,
+    /// Number of rows the diagram spans from row 0.
+    pub height: usize,
+    /// Participants by [`CallGraph::components_in_order`].
+    pub participants: Vec<ParticipantLayout>,
+    pub messages: Vec<MessageLayout>,
+    pub self_calls: Vec<SelfCallLayout>,
+    pub frames: Vec<FrameLayout>,
+    /// Boxes around groups of participants, from above their headers down
+    /// their lifelines.
+    pub groups: Vec<FrameLayout>,
+    pub dividers: Vec<DividerLayout>,
+    pub delays: Vec<DelayLayout>,
+    /// Names of the participants on other pages at the end of the messages
+    /// from or to them.
+    pub stubs: Vec<TextLayout>,
+    /// Title above the participant row.
+    pub title: Option<TextLayout>,
+    /// Caption below the lifelines.
+    pub caption: Option<TextLayout>,
+    /// Footer below the caption.
+    pub footer: Option<TextLayout>,
+    pub legend: Option<LegendLayout>,
+}
+
+impl SequenceLayout {
+    /// Lays out the sequence diagram of `callgraph`.
+    pub fn new(callgraph: &CallGraph, config: &PainterConfig) -> Self {
+        let mut builder = LayoutBuilder::new(callgraph, config);
+        builder.layout_participants();
+        let length = builder.layout_function_calls();
+        if config.footers {
+            builder.layout_repeated_participants(length);
+        }
+        builder.layout_lifelines(length);
+        builder.layout_groups();
+        builder.layout.resize();
+        builder.layout_surroundings();
+        builder.layout
+    }
+
+    /// Updates `width` and `height` to cover all the elements.
+    pub(crate) fn resize(&mut self) {
+        let mut width = 0;
+        let mut height = 0;
+        // extends the size to the exclusive `right` column and `bottom` row
+        let mut cover = |right: usize, bottom: usize| {
+            width = max(width, right);
+            height = max(height, bottom);
+        };
+        for participant in &self.participants {
+            for b in &participant.boxes {
+                cover(b.rec.right + 1, b.rec.bottom + 1);
+            }
+            for &(_, end) in &participant.lifeline {
+                cover(participant.center + 1, end);
+            }
+            for &row in &participant.continued {
+                cover(participant.center + 1, row + 1);
+            }
+        }
+        for m in &self.messages {
+            cover(max(m.from, m.to) + 1, m.row + 1);
+            cover(m.label.right(), m.label.bottom());
+        }
+        for s in &self.self_calls {
+            cover(s.columns().1, s.bottom + 1);
+        }
+        for frame in self.frames.iter().chain(&self.groups) {
+            cover(frame.rec.right + 1, frame.rec.bottom + 1);
+        }
+        let texts = [&self.title, &self.caption, &self.footer];
+        for text in texts.iter().filter_map(|text| text.as_ref()) {
+            cover(text.right(), text.bottom());
+        }
+        if let Some(legend) = &self.legend {
+            cover(legend.rec.right + 1, legend.rec.bottom + 1);
+        }
+        for divider in &self.dividers {
+            cover(divider.label.right() + 2, divider.row + 1);
+        }
+        for stub in &self.stubs {
+            cover(stub.right(), stub.bottom());
+        }
+        for delay in &self.delays {
+            cover(0, delay.rows.1);
+            if let Some(label) = &delay.label {
+                cover(label.right(), label.bottom());
+            }
+        }
+        self.width = width;
+        self.height = height;
+    }
+
+    /// Centers the title, caption, footer, legend and the labels of the
+    /// dividers and delays on `width`, and stretches the dividers across it.
+    pub(crate) fn center(&mut self) {
+        let width = self.width;
+        let center = |text: &mut TextLayout| {
+            text.left = (width - (text.right() - text.left)) / 2;
+        };
+        let mut texts = [&mut self.title, &mut self.caption, &mut self.footer];
+        for text in texts.iter_mut().filter_map(|text| text.as_mut()) {
+            center(text);
+        }
+        for divider in &mut self.dividers {
+            divider.left = 0;
+            divider.right = width;
+            center(&mut divider.label);
+        }
+        for delay in &mut self.delays {
+            if let Some(label) = &mut delay.label {
+                center(label);
+            }
+        }
+        if let Some(legend) = &mut self.legend {
+            let left = (width - (legend.rec.right + 1 - legend.rec.left)) / 2;
+            legend.move_to(left);
+        }
+    }
+}
+
+impl LegendLayout {
+    /// Moves the legend and its entries so that its box starts at column
+    /// `left`.
+    pub(crate) fn move_to(&mut self, left: usize) {
+        let old_left = self.rec.left;
+        let shift = |column: usize| column - old_left + left;
+        for entry in self.entries.iter_mut().map(|e| &mut e.message) {
+            entry.from = shift(entry.from);
+            entry.to = shift(entry.to);
+            entry.label.left = shift(entry.label.left);
+        }
+        self.rec.right = shift(self.rec.right);
+        self.rec.left = left;
+    }
+}
+
+/// Wraps `text` to lines of `width` chars. A space starting a wrapped line
+/// is dropped.
+pub(crate) fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    let mut count = 0;
+    let mut new_line = false;
+    for c in text.chars() {
+        if count == width {
+            lines.push(std::mem::take(&mut line));
+            count = 0;
+            new_line = true;
+        }
+        if new_line && c == ' ' {
+            new_line = false;
+            continue;
+        }
+        new_line = false;
+        line.push(c);
+        count += 1;
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// Intermediate state of laying out a [`SequenceLayout`].
+struct LayoutBuilder<'a> {
+    callgraph: &'a CallGraph,
+    config: &'a PainterConfig,
+    layout: SequenceLayout,
+    /// Map between component identifier to its index in participants
+    order: HashMap<&'a str, usize>,
+    /// Header boxes of the participants, including the created ones not
+    /// laid out yet
+    headers: Vec<BoxLayout>,
+    /// Created components whose box is not laid out yet
+    created: HashSet<&'a str>,
+    /// Nesting of each call among the self-calls of its component
+    levels: Vec<usize>,
+    /// Top row of the participant header boxes
+    header_top: usize,
+    bottom_boundary: usize,
+}
+
+impl<'a> LayoutBuilder<'a> {
+    fn new(callgraph: &'a CallGraph, config: &'a PainterConfig) -> Self {
+        let order = callgraph
+            .components_in_order
+            .iter()
+            .enumerate()
+            .map(|(i, component)| (component.as_str(), i))
+            .collect();
+        let header = &callgraph.header;
+        let title = config.title.as_ref().or(header.title.as_ref());
+        let created = callgraph
+            .func_calls
+            .iter()
+            .filter(|f| {
+                f.kind == MessageKind::Create && f.caller != f.callee.0
+            })
+            .map(|f| f.callee.0.as_str())
+            .collect();
+        LayoutBuilder {
+            callgraph,
+            config,
+            layout: SequenceLayout {
+                width: 0,
+                height: 0,
+                participants: Vec::new(),
+                messages: Vec::new(),
+                self_calls: Vec::new(),
+                frames: Vec::new(),
+                groups: Vec::new(),
+                dividers: Vec::new(),
+                delays: Vec::new(),
+                stubs: Vec::new(),
+                title: None,
+                caption: None,
+                footer: None,
+                legend: None,
+            },
+            order,
+            headers: Vec::new(),
+            created,
+            levels: self_call_levels(callgraph),
+            // the title takes the row above the headers and a blank one, and
+            // the top of the group boxes the row right above the headers
+            header_top: if title.is_some() { 3 } else { 1 }
+                + !callgraph.groups.is_empty() as usize,
+            bottom_boundary: 0,
+        }
+    }
+
+    /// Returns the label of the `index`-th function call `f`, with its number
+    /// and the metadata shown by the config.
+    fn message_label(&self, index: usize, f: &FunctionCall) -> String {
+        let mut label = f.callee.1.to_owned();
+        if let (true, Some(args)) = (self.config.show_args, &f.args) {
+            label = format!("{}({})", label, args);
+        }
+        if let (true, Some(timing)) = (self.config.show_timing, &f.timing) {
+            label = format!("{} [{}]", label, timing);
+        }
+        if let (true, Some(note)) =
+            (self.config.show_annotations, &f.annotation)
+        {
+            label = format!("{} // {}", label, note);
+        }
+
+        let autonumber =
+            self.config.autonumber.or(self.callgraph.header.autonumber);
+        let number = match autonumber {
+            None => return label,
+            Some(Numbering::Flat) => (index + 1).to_string(),
+            Some(Numbering::Hierarchical) => f
+                .number
+                .iter()
+                .map(|n| n.to_string())
+                .collect::<Vec<_>>()
+                .join("."),
+        };
+        format!("{} {}", number, label)
+    }
+
+    /// Returns the number of columns the participant `label` is wrapped to.
+    fn label_width(&self, label: &str) -> usize {
+        max(
+            min(self.config.max_participant_width, label.chars().count()),
+            1,
+        )
+    }
+
+    /// Returns the inner width of the participant box labeled `label`.
+    fn component_width(&self, label: &str) -> usize {
+        self.label_width(label) + 2 * self.config.header_padding
+    }
+
+    /// Returns the width the message `label` is wrapped to within
+    /// `available` columns.
+    fn message_width(&self, label: &str, available: usize) -> usize {
+        let width = min(available, self.config.max_label_width);
+        max(min(width, label.chars().count()), 1)
+    }
+
+    /// Returns the participant box of `component` with its top at `top`.
+    fn component_box(
+        &self,
+        component: &str,
+        left: usize,
+        top: usize,
+    ) -> BoxLayout {
+        let text = self.callgraph.label(component);
+        let padding = self.config.header_padding;
+        let label = TextLayout::new(
+            top + 1,
+            left + 1 + padding,
+            text,
+            self.label_width(text),
+        );
+        BoxLayout {
+            rec: Rectangle {
+                left,
+                right: left + self.component_width(text) + 1,
+                top,
+                bottom: label.bottom(),
+            },
+            label,
+        }
+    }
+
+    /// Places the participants from left to right, each as close to its left
+    /// neighbour as the message labels crossing the gap allow.
+    fn layout_participants(&mut self) {
+        let spacings = self.message_spacings();
+        let rooms = self.self_call_rooms();
+
+        let mut right_boundary = 0;
+        for (i, component) in
+            self.callgraph.components_in_order.iter().enumerate()
+        {
+            let width = self.component_width(self.callgraph.label(component));
+            let mut left = self.config.participant_gap + right_boundary;
+            // the self-calls of the left neighbour go right unless they fit
+            // on its left
+            if i > 0
+                && rooms[i - 1] > 0
+                && self.gap(i - 1, true) < rooms[i - 1]
+            {
+                let other_center = self.layout.participants[i - 1].center;
+                left = max(
+                    left,
+                    (other_center + rooms[i - 1])
+                        .saturating_sub(width.div_ceil(2)),
+                );
+            }
+            for (other, distance) in &spacings[i] {
+                let other_center = other
+                    .map_or(0, |other| self.layout.participants[other].center);
+                left = max(
+                    left,
+                    (other_center + distance)
+                        .saturating_sub(width.div_ceil(2)),
+                );
+            }
+            let header = self.component_box(component, left, self.header_top);
+            right_boundary = header.rec.right;
+
+            let mut participant = ParticipantLayout {
+                component: component.to_owned(),
+                label: self.callgraph.label(component).to_owned(),
+                center: (header.rec.left + header.rec.right) / 2,
+                boxes: Vec::new(),
+                lifeline: Vec::new(),
+                destroyed: None,
+                broken: Vec::new(),
+                continued: Vec::new(),
+            };
+            if !self.created.contains(component.as_str()) {
+                self.bottom_boundary =
+                    max(self.bottom_boundary, header.rec.bottom);
+                participant.boxes.push(header.clone());
+            }
+            self.layout.participants.push(participant);
+            self.headers.push(header);
+        }
+    }
+
+    /// Layout pass over the messages: returns for each component in order
+    /// the minimal distances between its center and the centers of the
+    /// components on its left, so that the message labels crossing the gaps
+    /// fit on one line. `None` stands for the outside caller at column 0.
+    fn message_spacings(&self) -> Vec<Vec<(Option<usize>, usize)>> {
+        let mut spacings =
+            vec![Vec::new(); self.callgraph.components_in_order.len()];
+        for (i, f) in self.callgraph.func_calls.iter().enumerate() {
+            let label = self.message_label(i, f);
+            let label_width = self.message_width(&label, usize::MAX);
+            let called = self.order[f.callee.0.as_str()];
+
+            // self-calls are spaced by self_call_rooms
+            if f.caller == f.callee.0 {
+                continue;
+            }
+
+            // label starts 2 columns away from the lifeline and ends 2
+            // columns before the arrowhead
+            let mut distance = label_width + 4;
+            if f.kind == MessageKind::Create {
+                let label = self.callgraph.label(&f.callee.0);
+                distance += self.component_width(label).div_ceil(2) + 1;
+            }
+            match self.order.get(f.caller.as_str()) {
+                Some(&calling) if calling > called => {
+                    spacings[calling].push((Some(called), distance))
+                }
+                calling => spacings[called].push((calling.copied(), distance)),
+            }
+        }
+        spacings
+    }
+
+    /// Returns for each component in order the columns its widest self-call
+    /// loop and label take from its lifeline, with 2 columns of margin, or
+    /// 0 without self-calls.
+    fn self_call_rooms(&self) -> Vec<usize> {
+        let mut rooms = vec![0; self.callgraph.components_in_order.len()];
+        for (i, f) in self.callgraph.func_calls.iter().enumerate() {
+            if f.caller == f.callee.0 {
+                let label = self.message_label(i, f);
+                let called = self.order[f.callee.0.as_str()];
+                let room = self.self_call_extent(i, &label) + 2;
+                rooms[called] = max(rooms[called], room);
+            }
+        }
+        rooms
+    }
+
+    /// Returns the columns the self-call loop of the `index`-th call and its
+    /// `label` take from the lifeline: the loop is offset by 2 columns per
+    /// level of nesting, and the label follows 2 columns after it.
+    fn self_call_extent(&self, index: usize, label: &str) -> usize {
+        let label_width =
+            self.message_width(label, self.config.max_label_width);
+        2 * self.levels[index] + self.config.self_call_width + 2 + label_width
+    }
+
+    /// Returns the columns between the lifeline of the `i`-th participant
+    /// and the lifeline of its neighbour on the `left` or right side, the
+    /// left edge of the diagram, or `usize::MAX` on the right of the last
+    /// one.
+    fn gap(&self, i: usize, left: bool) -> usize {
+        let participants = &self.layout.participants;
+        let center = participants[i].center;
+        match (left, i.checked_sub(1), participants.get(i + 1)) {
+            (true, Some(other), _) => center - participants[other].center,
+            (true, None, _) => center,
+            (false, _, Some(other)) => other.center - center,
+            (false, _, None) => usize::MAX,
+        }
+    }
+
+    /// Lays out the title, caption, footer and legend around the diagram of
+    /// `self.layout.width` columns, widening it if any of them is wider. All
+    /// are centered on the final width, as well as the dividers and the
+    /// labels of the delays.
+    fn layout_surroundings(&mut self) {
+        let header = &self.callgraph.header;
+        let config = self.config;
+
+        let mut top = self.layout.height + 1;
+        let mut below = |text: Option<&String>| {
+            text.map(|text| {
+                let text = TextLayout::new(top, 0, text, usize::MAX);
+                top = text.bottom() + 1;
+                text
+            })
+        };
+        let caption =
+            below(config.caption.as_ref().or(header.caption.as_ref()));
+        let footer = below(config.footer.as_ref().or(header.footer.as_ref()));
+        let title = config.title.as_ref().or(header.title.as_ref());
+        self.layout.title =
+            title.map(|title| TextLayout::new(1, 0, title, usize::MAX));
+        self.layout.caption = caption;
+        self.layout.footer = footer;
+        if config.legend {
+            self.layout.legend = self.legend(top);
+        }
+        self.layout.resize();
+        self.layout.center();
+    }
+
+    /// Returns the legend of the arrow styles used by the messages, with its
+    /// top at `top`, or `None` if there is no message.
+    fn legend(&self, top: usize) -> Option<LegendLayout> {
+        let styles = [
+            (MessageKind::Sync, ArrowHead::Filled, "call"),
+            (MessageKind::Async, ArrowHead::Open, "async call"),
+            (MessageKind::Create, ArrowHead::Filled, "create"),
+            (MessageKind::Destroy, ArrowHead::Filled, "destroy"),
+        ];
+        let mut entries = Vec::new();
+        for (kind, head, meaning) in styles.iter() {
+            if !self.callgraph.func_calls.iter().any(|f| f.kind == *kind) {
+                continue;
+            }
+            let row = top + 1 + entries.len();
+            entries.push(LegendEntry {
+                kind: *kind,
+                message: MessageLayout {
+                    row,
+                    from: 1,
+                    to: 4,
+                    endpoints: (None, None),
+                    head: *head,
+                    label: TextLayout::new(row, 7, meaning, usize::MAX),
+                },
+            });
+        }
+        let right = entries.iter().map(|e| e.message.label.right()).max()? + 1;
+        Some(LegendLayout {
+            rec: Rectangle {
+                left: 0,
+                right,
+                top,
+                bottom: top + entries.len() + 1,
+            },
+            entries,
+        })
+    }
+
+    /// Lays out all the function calls, returns the expected bottom of the
+    /// lifelines.
+    fn layout_function_calls(&mut self) -> usize {
+        let callgraph = self.callgraph;
+        let mut markers = callgraph.markers.iter().peekable();
+        let mut loops = callgraph.loops.iter().peekable();
+        // loops around the call being laid out, the innermost last
+        let mut open = Vec::new();
+        for (i, f) in callgraph.func_calls.iter().enumerate() {
+            while open
+                .last()
+                .is_some_and(|(l, _): &(&Loop, _)| l.calls.end == i)
+            {
+                let (l, start) = open.pop().unwrap();
+                self.layout_loop(l, start);
+            }
+            while let Some((_, marker)) = markers.next_if(|(at, _)| *at == i) {
+                self.layout_marker(marker);
+            }
+            while let Some(l) = loops.next_if(|l| l.calls.start == i) {
+                open.push((l, self.loop_start()));
+            }
+
+            if let Some(every) = self.config.repeat_header_every {
+                if i > 0 && every > 0 && i % every == 0 {
+                    let top =
+                        self.bottom_boundary + self.config.vertical_margin;
+                    self.bottom_boundary =
+                        self.layout_repeated_participants(top);
+                }
+            }
+
+            let label = self.message_label(i, f);
+            if f.caller != f.callee.0 {
+                self.layout_cross_component_call(f, &label);
+            } else {
+                self.layout_same_component_call(i, f, &label);
+            }
+            if f.kind == MessageKind::Destroy {
+                let called = self.order[f.callee.0.as_str()];
+                self.layout.participants[called].destroyed =
+                    Some(self.bottom_boundary);
+            }
+        }
+        while let Some((l, start)) = open.pop() {
+            self.layout_loop(l, start);
+        }
+        for (_, marker) in markers {
+            self.layout_marker(marker);
+        }
+        self.bottom_boundary + self.config.vertical_margin
+    }
+
+    /// Lays out a divider or a delay below the last message.
+    fn layout_marker(&mut self, marker: &Marker) {
+        self.bottom_boundary += self.config.vertical_margin;
+        match marker {
+            Marker::Divider(name) => {
+                let row = self.bottom_boundary + 1;
+                // the label is padded by a space on both sides
+                let label = if name.is_empty() {
+                    String::new()
+                } else {
+                    format!(" {} ", name)
+                };
+                self.layout.dividers.push(DividerLayout {
+                    row,
+                    left: 0,
+                    right: 0,
+                    label: TextLayout::new(row, 0, &label, usize::MAX),
+                });
+                self.bottom_boundary = row;
+            }
+            Marker::Delay(description) => {
+                let top = self.bottom_boundary + 1;
+                let label = description.as_ref().map(|description| {
+                    TextLayout::new(top + 1, 0, description, usize::MAX)
+                });
+                let bottom =
+                    label.as_ref().map_or(top + 1, |l| l.bottom()) + 1;
+                self.layout.delays.push(DelayLayout {
+                    rows: (top, bottom),
+                    label,
+                });
+                self.bottom_boundary = bottom;
+            }
+        }
+    }
+
+    /// Starts the frame of a loop below the last message. Returns where it
+    /// starts: its top row, and the numbers of messages, self-calls and
+    /// frames laid out before it.
+    fn loop_start(&mut self) -> LoopStart {
+        self.bottom_boundary += max(self.config.vertical_margin, 1);
+        LoopStart {
+            top: self.bottom_boundary,
+            messages: self.layout.messages.len(),
+            self_calls: self.layout.self_calls.len(),
+            frames: self.layout.frames.len(),
+        }
+    }
+
+    /// Lays out the frame of the loop `l` from `start` below the last
+    /// message, around all that was laid out since.
+    fn layout_loop(&mut self, l: &Loop, start: LoopStart) {
+        let layout = &self.layout;
+        // columns of the content, with `right` exclusive
+        let mut left = usize::MAX;
+        let mut right = 0;
+        for m in &layout.messages[start.messages..] {
+            let (caller, callee) = m.endpoints;
+            for p in caller.iter().chain(&callee) {
+                let center = layout.participants[*p].center;
+                left = min(left, center);
+                right = max(right, center + 1);
+            }
+            left = min(left, min(m.from, m.to));
+            right = max(right, max(max(m.from, m.to) + 1, m.label.right()));
+        }
+        for s in &layout.self_calls[start.self_calls..] {
+            let (s_left, s_right) = s.columns();
+            left = min(left, s_left);
+            right = max(right, s_right);
+        }
+        for frame in &layout.frames[start.frames..] {
+            left = min(left, frame.rec.left);
+            right = max(right, frame.rec.right + 1);
+        }
+        let left = left.saturating_sub(2);
+
+        let label = format!(" loop ×{} ", l.count);
+        let label = TextLayout::new(start.top, left + 2, &label, usize::MAX);
+        self.bottom_boundary += max(self.config.vertical_margin, 1);
+        self.layout.frames.push(FrameLayout {
+            rec: Rectangle {
+                left,
+                right: max(right + 1, label.right() + 1),
+                top: start.top,
+                bottom: self.bottom_boundary,
+            },
+            label,
+        });
+    }
+
+    fn layout_cross_component_call(&mut self, f: &FunctionCall, label: &str) {
+        let called = self.order[f.callee.0.as_str()];
+        let calling_center = match self.order.get(f.caller.as_str()) {
+            Some(&calling) => self.layout.participants[calling].center,
+            None => 0,
+        };
+        let mut called_center = self.layout.participants[called].center;
+        let created = f.kind == MessageKind::Create
+            && self.created.remove(f.callee.0.as_str());
+
+        // space for arrow
+        if calling_center < called_center {
+            called_center -= 1;
+        } else {
+            called_center += 1;
+        }
+        // the arrow of a create message points to the box edge
+        if created {
+            let header = &self.headers[called].rec;
+            if calling_center < called_center {
+                called_center = header.left - 1;
+            } else {
+                called_center = header.right + 1;
+            }
+        }
+
+        let arrow_left = min(called_center, calling_center);
+        let arrow_right = max(called_center, calling_center);
+        let label_width = self.message_width(
+            label,
+            (arrow_right - arrow_left).saturating_sub(3),
+        );
+        let label_lines = wrap(label, label_width).len();
+
+        self.bottom_boundary += self.config.vertical_margin;
+        let mut row = self.bottom_boundary + label_lines;
+
+        if created {
+            let header = &self.headers[called];
+            let height = header.rec.bottom - header.rec.top;
+            row = max(row, self.bottom_boundary + height / 2);
+            let created_box = self.component_box(
+                &f.callee.0,
+                header.rec.left,
+                row - height / 2,
+            );
+            self.bottom_boundary = created_box.rec.bottom;
+            self.layout.participants[called].boxes.push(created_box);
+        }
+
+        self.layout.messages.push(MessageLayout {
+            row,
+            from: calling_center,
+            to: called_center,
+            endpoints: (
+                self.order.get(f.caller.as_str()).copied(),
+                Some(called),
+            ),
+            head: arrow_head(f),
+            label: TextLayout::new(
+                row - label_lines,
+                arrow_left + 2,
+                label,
+                label_width,
+            ),
+        });
+        self.bottom_boundary = max(row, self.bottom_boundary);
+    }
+
+    /// Lays out the self-call loop of the `index`-th call `f`, on the right
+    /// of the lifeline if its label fits before the next lifeline, or else
+    /// on the left if it fits there.
+    fn layout_same_component_call(
+        &mut self,
+        index: usize,
+        f: &FunctionCall,
+        label: &str,
+    ) {
+        let called = self.order[f.callee.0.as_str()];
+        let column = self.layout.participants[called].center;
+        let room = self.self_call_extent(index, label) + 2;
+        let on_left =
+            self.gap(called, false) < room && self.gap(called, true) >= room;
+
+        self.bottom_boundary += self.config.vertical_margin;
+        let top = self.bottom_boundary;
+
+        let label_width =
+            self.message_width(label, self.config.max_label_width);
+        let reach = 2 * self.levels[index] + self.config.self_call_width;
+        let (right, label_left) = if on_left {
+            (column - reach, column - reach - 2 - label_width)
+        } else {
+            (column + reach, column + reach + 2)
+        };
+        let label = TextLayout::new(top + 1, label_left, label, label_width);
+        let bottom = label.bottom();
+
+        self.layout.self_calls.push(SelfCallLayout {
+            column,
+            right,
+            top,
+            bottom,
+            head: arrow_head(f),
+            label,
+        });
+        self.bottom_boundary = bottom;
+    }
+
+    /// Repeats the boxes of the components alive at row `top`, reusing
+    /// their header [`Rectangle`]s. Returns the bottom of the tallest box.
+    fn layout_repeated_participants(&mut self, top: usize) -> usize {
+        let mut bottom_boundary = top;
+        for (i, component) in
+            self.callgraph.components_in_order.iter().enumerate()
+        {
+            if self.created.contains(component.as_str())
+                || self.layout.participants[i].destroyed.is_some()
+            {
+                continue;
+            }
+            let header = &self.headers[i].rec;
+            let repeated = self.component_box(component, header.left, top);
+            bottom_boundary = max(bottom_boundary, repeated.rec.bottom);
+            self.layout.participants[i].boxes.push(repeated);
+        }
+        bottom_boundary
+    }
+
+    /// Lays out the boxes around the groups of participants, from the row
+    /// above their headers down to the bottom of their lifelines.
+    fn layout_groups(&mut self) {
+        for group in &self.callgraph.groups {
+            let members: Vec<usize> = group
+                .components
+                .iter()
+                .filter_map(|c| self.order.get(c.as_str()).copied())
+                .collect();
+            let headers = members.iter().map(|&i| &self.headers[i].rec);
+            let left = match headers.clone().map(|rec| rec.left).min() {
+                Some(left) => left.saturating_sub(2),
+                None => continue,
+            };
+            let right = headers.map(|rec| rec.right).max().unwrap_or(0) + 2;
+            let mut bottom = 0;
+            for &i in &members {
+                let participant = &self.layout.participants[i];
+                let boxes = participant.boxes.iter().map(|b| b.rec.bottom + 1);
+                let lifeline = participant.lifeline.iter().map(|&(_, e)| e);
+                bottom = max(bottom, boxes.chain(lifeline).max().unwrap_or(0));
+            }
+
+            let top = self.header_top - 1;
+            let label = format!(" {} ", group.label);
+            let label = TextLayout::new(top, left + 2, &label, usize::MAX);
+            self.layout.groups.push(FrameLayout {
+                rec: Rectangle {
+                    left,
+                    right: max(right, label.right() + 1),
+                    top,
+                    bottom,
+                },
+                label,
+            });
+        }
+    }
+
+    /// Lays out the lifelines down to `bottom`, interrupted by the repeated
+    /// boxes and the labels of the messages passing over them, and broken
+    /// over the delays.
+    fn layout_lifelines(&mut self, bottom: usize) {
+        let delays = &self.layout.delays;
+        let messages = &self.layout.messages;
+        for (i, participant) in self.layout.participants.iter_mut().enumerate()
+        {
+            let end = participant.destroyed.unwrap_or(bottom);
+            let mut boxes = participant.boxes.iter();
+            let mut start = match boxes.next() {
+                Some(header) => header.rec.bottom,
+                None => continue,
+            };
+            for b in boxes {
+                participant.lifeline.push((start, b.rec.top));
+                start = b.rec.bottom;
+            }
+            if start < end {
+                participant.lifeline.push((start, end));
+            }
+            // a column of margin is kept on both sides of the labels
+            let center = participant.center;
+            let labels = messages
+                .iter()
+                .filter(|m| {
+                    m.endpoints.0 != Some(i) && m.endpoints.1 != Some(i)
+                })
+                .map(|m| &m.label)
+                .filter(|l| l.left <= center + 1 && center <= l.right());
+            for label in labels {
+                interrupt(&mut participant.lifeline, label);
+            }
+            let alive = participant.boxes.first().map_or(end, |b| b.rec.top);
+            participant.broken = delays
+                .iter()
+                .map(|delay| delay.rows)
+                .filter(|&(top, bottom)| alive < top && bottom <= end)
+                .collect();
+        }
+    }
+}
+
+/// Where the frame of a loop starts, see [`LayoutBuilder::loop_start`].
+struct LoopStart {
+    top: usize,
+    messages: usize,
+    self_calls: usize,
+    frames: usize,
+}
+
+/// Cuts the rows of `label` out of the `lifeline` segments.
+fn interrupt(lifeline: &mut Vec<(usize, usize)>, label: &TextLayout) {
+    let (top, bottom) = (label.top, label.bottom());
+    *lifeline = lifeline
+        .iter()
+        .flat_map(|&(start, end)| {
+            if top < end && start + 1 < bottom {
+                vec![(start, top), (bottom - 1, end)]
+            } else {
+                vec![(start, end)]
+            }
+        })
+        .filter(|&(start, end)| start + 1 < end)
+        .collect();
+}
+
+/// Returns for each call of `callgraph` the number of self-calls of its
+/// component it is nested in.
+fn self_call_levels(callgraph: &CallGraph) -> Vec<usize> {
+    // the components and numbers of the self-calls above the call, the
+    // innermost last
+    let mut stack: Vec<(&str, &[usize])> = Vec::new();
+    let mut levels = Vec::new();
+    for f in &callgraph.func_calls {
+        if f.caller != f.callee.0 {
+            levels.push(0);
+            continue;
+        }
+        while stack.last().is_some_and(|(_, number)| {
+            !(f.number.len() > number.len() && f.number.starts_with(number))
+        }) {
+            stack.pop();
+        }
+        let component = f.callee.0.as_str();
+        levels.push(stack.iter().filter(|(c, _)| *c == component).count());
+        stack.push((component, &f.number));
+    }
+    levels
+}
+
+/// Returns the arrowhead of the message drawn for `f`.
+fn arrow_head(f: &FunctionCall) -> ArrowHead {
+    match f.kind {
+        MessageKind::Async => ArrowHead::Open,
+        _ => ArrowHead::Filled,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_self_call_layout() {
+        let txt =
+            fs::read_to_string("./test/callgraph_self_call.txt").unwrap();
+
+        let callgraph = CallGraph::new(&txt).unwrap();
+        let layout =
+            SequenceLayout::new(&callgraph, &PainterConfig::default());
+
+        let centers: Vec<usize> =
+            layout.participants.iter().map(|p| p.center).collect();
+        assert_eq!(centers, [10, 22]);
+        assert_eq!(layout.participants[1].lifeline, [(3, 22)]);
+
+        let rows: Vec<usize> = layout.messages.iter().map(|m| m.row).collect();
+        assert_eq!(rows, [6, 9, 20]);
+        assert_eq!(layout.messages[1].from, 10);
+        assert_eq!(layout.messages[1].to, 21);
+        assert_eq!(layout.messages[1].label.lines, ["func_2"]);
+
+        let loops: Vec<(usize, usize)> = layout
+            .self_calls
+            .iter()
+            .map(|s| (s.top, s.bottom))
+            .collect();
+        assert_eq!(loops, [(11, 13), (15, 17)]);
+        assert_eq!(layout.self_calls[0].right, 27);
+        assert_eq!(layout.self_calls[0].label.left, 29);
+        assert_eq!(layout.self_calls[0].label.top, 12);
+
+        assert_eq!((layout.width, layout.height), (35, 22));
+    }
+}
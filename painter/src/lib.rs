@@ -1,229 +1,765 @@
 //! This crate includes [`Painter`] type, which processes callgraph text and draw UML sequence
-//! diagram using [`Canvas`].
+//! diagram, call tree, collaboration diagram or class diagram, or flowchart
+//! text and draw flowchart, using [`Canvas`].
 
 use canvas::*;
-use std::{
-    cmp::{max, min},
-    collections::HashMap,
-};
+use std::cmp::max;
 
 pub mod callgraph;
-use callgraph::{CallGraph, FunctionCall};
+pub mod classes;
+pub mod collaboration;
+pub mod config;
+pub mod filter;
+pub mod flowchart;
+pub mod fold;
+pub mod layout;
+pub mod page;
+pub mod render;
+pub mod tree;
+use callgraph::{CallGraph, ParseError, ParseOptions, Style};
+use classes::ClassDiagramLayout;
+use collaboration::CollaborationLayout;
+use config::{Diagram, PainterConfig};
+use filter::{Filter, COLLAPSED, COLLAPSED_ASCII};
+use flowchart::{Flowchart, FlowchartLayout};
+use layout::SequenceLayout;
+use page::Translate;
+use render::{
+    CanvasRenderer, ClassRenderer, CollaborationRenderer, FlowchartRenderer,
+    Renderer,
+};
+use tree::CallTree;
 
-/// A translation layer from [`CallGraph`] to drawing on [`Canvas`].
+/// A translation layer from [`CallGraph`] to drawing on [`Canvas`], through
+/// the [`SequenceLayout`] of the diagram.
 pub struct Painter {
-    /// Map between component identifier to its [`Rectangle`]
-    components: HashMap<String, Rectangle>,
+    /// Drawing settings
+    config: PainterConfig,
 }
 
-const EXTRA_VERTICAL_MARGIN: usize = 2;
-const DEFAULT_SELF_CALL_WIDTH: usize = 5;
-
 impl Painter {
-    pub fn new() -> Self {
-        Painter {
-            components: HashMap::new(),
+    pub fn new(config: PainterConfig) -> Self {
+        Painter { config }
+    }
+
+    /// Computes the geometry of the sequence diagram of `callgraph`.
+    pub fn layout(&self, callgraph: &CallGraph) -> SequenceLayout {
+        SequenceLayout::new(callgraph, &self.config)
+    }
+
+    /// Draws the diagram of `callgraph_str` on `canvas`, or returns the
+    /// errors of the callgraph text without drawing. Pages of a sequence
+    /// diagram are drawn one below the other, a blank row apart. The text
+    /// of a flowchart is parsed as such instead.
+    pub fn draw(
+        &mut self,
+        canvas: &mut Canvas,
+        callgraph_str: &str,
+    ) -> Result<(), Vec<ParseError>> {
+        if self.config.diagram == Diagram::Flowchart {
+            let flowchart = self.parse_flowchart(callgraph_str)?;
+            let layout = FlowchartLayout::new(&flowchart, &self.config);
+            CanvasRenderer::new(canvas).render_flowchart(&layout);
+            if self.config.style.or(flowchart.style) == Some(Style::Ascii) {
+                canvas.asciify();
+            }
+            return Ok(());
         }
+        let callgraph = self.parse(callgraph_str)?;
+        match self.config.diagram {
+            Diagram::Sequence => {
+                let mut top = 0;
+                for mut page in self.pages(&callgraph) {
+                    page.translate(top as isize, 0);
+                    top = page.height + 1;
+                    CanvasRenderer::new(canvas).render(&page);
+                }
+            }
+            Diagram::Tree => {
+                let lines = self.tree_lines(&callgraph);
+                for (row, line) in lines.iter().enumerate() {
+                    canvas.draw_text(&(row, 0), line);
+                }
+            }
+            Diagram::Collaboration => {
+                let layout =
+                    CollaborationLayout::new(&callgraph, &self.config);
+                CanvasRenderer::new(canvas).render_collaboration(&layout);
+            }
+            Diagram::Class => {
+                let layout = ClassDiagramLayout::new(&callgraph, &self.config);
+                CanvasRenderer::new(canvas).render_classes(&layout);
+            }
+            Diagram::Flowchart => unreachable!("flowcharts are drawn above"),
+        }
+        if self.ascii(&callgraph) {
+            canvas.asciify();
+        }
+        Ok(())
     }
 
-    /// Draws all the component rectangles on the `canvas`
-    fn draw_components(&mut self, canvas: &mut Canvas, callgraph: &CallGraph) {
-        let mut right_boundary = 0;
-        let max_rec_width = 20;
-        let horizontal_gap = 5;
-        for component in &callgraph.components_in_order {
-            let width = min(max_rec_width, component.len());
-            let rec = Rectangle {
-                left: horizontal_gap + right_boundary,
-                right: width + 1 + horizontal_gap + right_boundary,
-                top: 1,
-                bottom: (component.len() - 1) / width + 3,
-            };
-            canvas.draw_rectangle_with_label(&rec, &component);
-            right_boundary = rec.right;
-            self.components.insert(component.to_owned(), rec);
+    /// Draws each page of the sequence diagram of `callgraph_str` on its own
+    /// [`Canvas`], or returns the errors of the callgraph text. Other
+    /// diagrams are drawn on a single page.
+    pub fn draw_pages(
+        &mut self,
+        callgraph_str: &str,
+    ) -> Result<Vec<Canvas>, Vec<ParseError>> {
+        if self.config.diagram == Diagram::Flowchart {
+            let flowchart = self.parse_flowchart(callgraph_str)?;
+            let layout = FlowchartLayout::new(&flowchart, &self.config);
+            let mut canvas =
+                Canvas::new(max(layout.width, 1), max(layout.height, 1));
+            CanvasRenderer::new(&mut canvas).render_flowchart(&layout);
+            if self.config.style.or(flowchart.style) == Some(Style::Ascii) {
+                canvas.asciify();
+            }
+            canvas.fill_boundary();
+            return Ok(vec![canvas]);
         }
+        let callgraph = self.parse(callgraph_str)?;
+        let ascii = self.ascii(&callgraph);
+        let mut canvases: Vec<Canvas> = match self.config.diagram {
+            Diagram::Sequence => self
+                .pages(&callgraph)
+                .iter()
+                .map(|page| {
+                    let mut canvas =
+                        Canvas::new(max(page.width, 1), max(page.height, 1));
+                    CanvasRenderer::new(&mut canvas).render(page);
+                    canvas
+                })
+                .collect(),
+            Diagram::Tree => {
+                let lines = self.tree_lines(&callgraph);
+                let width = lines.iter().map(|l| l.chars().count()).max();
+                let mut canvas = Canvas::new(
+                    max(width.unwrap_or(0), 1),
+                    max(lines.len(), 1),
+                );
+                for (row, line) in lines.iter().enumerate() {
+                    canvas.draw_text(&(row, 0), line);
+                }
+                vec![canvas]
+            }
+            Diagram::Collaboration => {
+                let layout =
+                    CollaborationLayout::new(&callgraph, &self.config);
+                let mut canvas =
+                    Canvas::new(max(layout.width, 1), max(layout.height, 1));
+                CanvasRenderer::new(&mut canvas).render_collaboration(&layout);
+                vec![canvas]
+            }
+            Diagram::Class => {
+                let layout = ClassDiagramLayout::new(&callgraph, &self.config);
+                let mut canvas =
+                    Canvas::new(max(layout.width, 1), max(layout.height, 1));
+                CanvasRenderer::new(&mut canvas).render_classes(&layout);
+                vec![canvas]
+            }
+            Diagram::Flowchart => unreachable!("flowcharts are drawn above"),
+        };
+        for canvas in &mut canvases {
+            if ascii {
+                canvas.asciify();
+            }
+            canvas.fill_boundary();
+        }
+        Ok(canvases)
     }
 
-    fn draw_cross_component_call(
+    fn parse(
         &self,
-        canvas: &mut Canvas,
-        f: &FunctionCall,
-        mut bottom_boundary: usize,
-    ) -> usize {
-        let virtual_rec = Rectangle {
-            left: 0,
-            right: 0,
-            top: 0,
-            bottom: 0,
+        callgraph_str: &str,
+    ) -> Result<CallGraph, Vec<ParseError>> {
+        let options = ParseOptions {
+            tab_width: self.config.tab_width,
+            separators: self.config.separators.clone(),
+            component_segments: self.config.component_segments,
         };
+        let mut callgraph = CallGraph::with_options(callgraph_str, &options)?;
+        let filter = Filter {
+            max_depth: self.config.max_depth,
+            include: self.config.include.clone(),
+            exclude: self.config.exclude.clone(),
+            hide_self_calls: self.config.hide_self_calls,
+            collapse_excluded: self.config.collapse_excluded,
+        };
+        if filter != Filter::default() {
+            callgraph.filter(&filter);
+        }
+        let collapsed = callgraph.components.contains_key(COLLAPSED);
+        if collapsed && self.ascii(&callgraph) {
+            let participant = callgraph
+                .participants
+                .entry(COLLAPSED.to_string())
+                .or_default();
+            participant.display_name = Some(COLLAPSED_ASCII.to_string());
+        }
+        if let Some(min_count) = self.config.fold_loops {
+            callgraph.fold_loops(min_count);
+        }
+        Ok(callgraph)
+    }
 
-        let component = f.0.to_owned();
-        let func = f.1.to_owned();
-
-        let called_rec = self.components.get(&func.0).unwrap();
-        let calling_rec =
-            self.components.get(&component).unwrap_or(&virtual_rec);
+    fn parse_flowchart(
+        &self,
+        flowchart_str: &str,
+    ) -> Result<Flowchart, Vec<ParseError>> {
+        let options = ParseOptions {
+            tab_width: self.config.tab_width,
+            ..Default::default()
+        };
+        Flowchart::with_options(flowchart_str, &options)
+    }
 
-        let calling_center = (calling_rec.left + calling_rec.right) / 2;
-        let mut called_center = (called_rec.left + called_rec.right) / 2;
+    /// Returns the lines of the call tree of `callgraph`.
+    fn tree_lines(&self, callgraph: &CallGraph) -> Vec<String> {
+        CallTree::new(callgraph).lines(self.config.call_counts)
+    }
 
-        // space for arrow
-        if calling_center < called_center {
-            called_center = called_center - 1;
-        } else {
-            called_center = called_center + 1;
+    /// Lays out `callgraph`, split into pages of rows, then of columns, by
+    /// the limits of the config.
+    fn pages(&self, callgraph: &CallGraph) -> Vec<SequenceLayout> {
+        let mut pages = vec![self.layout(callgraph)];
+        if let Some(max_height) = self.config.max_height {
+            pages = pages
+                .iter()
+                .flat_map(|p| p.split_rows(max_height))
+                .collect();
         }
+        if let Some(max_width) = self.config.max_width {
+            let gutter = self.config.page_gutter;
+            let ellipsis = if self.ascii(callgraph) { "..." } else { "…" };
+            pages = pages
+                .iter()
+                .flat_map(|p| p.split_columns(max_width, gutter, ellipsis))
+                .collect();
+        }
+        pages
+    }
 
-        let label_height = (func.1.len() - 1)
-            / (max(called_center, calling_center)
-                - min(called_center, calling_center)
-                - 1)
-            + 1;
+    /// Returns whether `callgraph` is drawn with plain ASCII characters.
+    fn ascii(&self, callgraph: &CallGraph) -> bool {
+        self.config.style.or(callgraph.header.style) == Some(Style::Ascii)
+    }
+}
 
-        bottom_boundary += EXTRA_VERTICAL_MARGIN;
+#[cfg(test)]
+mod test {
+    use super::*;
+    use callgraph::Numbering;
+    use canvas::Canvas;
+    use std::fs;
+
+    #[test]
+    fn test_callgraph() {
+        let txt = fs::read_to_string("./test/callgraph.txt").unwrap();
 
-        canvas.draw_line_with_label(
-            (bottom_boundary + label_height, calling_center),
-            (bottom_boundary + label_height, called_center),
-            &func.1,
-            true,
-        );
+        let mut canvas = Canvas::new(500, 500);
+
+        let mut painter = Painter::new(PainterConfig::default());
+
+        painter.draw(&mut canvas, &txt).unwrap();
 
-        bottom_boundary + label_height
+        canvas.reset_boundary();
+        canvas.print();
+        let res = fs::read_to_string("./test/callgraph_res.txt").unwrap();
+        assert_eq!(canvas.to_string(), res);
     }
 
-    fn draw_same_component_call(
-        &self,
-        canvas: &mut Canvas,
-        f: &FunctionCall,
-        mut bottom_boundary: usize,
-    ) -> usize {
-        let func = f.1.to_owned();
+    #[test]
+    fn test_callgraph_self_call() {
+        let txt =
+            fs::read_to_string("./test/callgraph_self_call.txt").unwrap();
+
+        let mut canvas = Canvas::new(500, 500);
+
+        let mut painter = Painter::new(PainterConfig::default());
+
+        painter.draw(&mut canvas, &txt).unwrap();
+
+        canvas.reset_boundary();
+        canvas.print();
+        let res =
+            fs::read_to_string("./test/callgraph_self_call_res.txt").unwrap();
+        assert_eq!(canvas.to_string(), res);
+    }
+
+    #[test]
+    fn test_callgraph_participants() {
+        let txt =
+            fs::read_to_string("./test/callgraph_participants.txt").unwrap();
 
-        let called_rec = self.components.get(&func.0).unwrap();
+        let mut canvas = Canvas::new(500, 500);
 
-        let called_center = (called_rec.left + called_rec.right) / 2;
+        let mut painter = Painter::new(PainterConfig::default());
 
-        bottom_boundary += EXTRA_VERTICAL_MARGIN;
+        painter.draw(&mut canvas, &txt).unwrap();
 
-        canvas.draw_line(
-            &(bottom_boundary, called_center),
-            &(bottom_boundary, called_center + DEFAULT_SELF_CALL_WIDTH),
-        );
+        canvas.reset_boundary();
+        canvas.print();
+        let res = fs::read_to_string("./test/callgraph_participants_res.txt")
+            .unwrap();
+        assert_eq!(canvas.to_string(), res);
+    }
 
-        canvas.draw_point(
-            &(bottom_boundary, called_center + DEFAULT_SELF_CALL_WIDTH),
-            '┐',
-        );
+    #[test]
+    fn test_callgraph_autonumber() {
+        let txt =
+            fs::read_to_string("./test/callgraph_self_call.txt").unwrap();
 
-        let label_height = (func.1.len() - 1) / (DEFAULT_SELF_CALL_WIDTH) + 1;
+        let mut canvas = Canvas::new(500, 500);
 
-        canvas.draw_line_with_label(
-            (bottom_boundary, called_center + DEFAULT_SELF_CALL_WIDTH),
-            (
-                bottom_boundary + label_height + 2,
-                called_center + DEFAULT_SELF_CALL_WIDTH,
-            ),
-            &func.1,
-            false,
-        );
+        let mut painter = Painter::new(PainterConfig {
+            autonumber: Some(Numbering::Hierarchical),
+            ..Default::default()
+        });
 
-        bottom_boundary += label_height + 2;
+        painter.draw(&mut canvas, &txt).unwrap();
 
-        canvas.draw_arrowed_line(
-            &(bottom_boundary, called_center + DEFAULT_SELF_CALL_WIDTH),
-            &(bottom_boundary, called_center + 1),
-        );
+        canvas.reset_boundary();
+        canvas.print();
+        let res =
+            fs::read_to_string("./test/callgraph_autonumber_res.txt").unwrap();
+        assert_eq!(canvas.to_string(), res);
+    }
+
+    #[test]
+    fn test_callgraph_message_kind() {
+        let txt =
+            fs::read_to_string("./test/callgraph_message_kind.txt").unwrap();
+
+        let mut canvas = Canvas::new(500, 500);
 
-        canvas.draw_point(
-            &(bottom_boundary, called_center + DEFAULT_SELF_CALL_WIDTH),
-            '┘',
-        );
+        let mut painter = Painter::new(PainterConfig::default());
 
-        bottom_boundary
+        painter.draw(&mut canvas, &txt).unwrap();
+
+        canvas.reset_boundary();
+        canvas.print();
+        let res = fs::read_to_string("./test/callgraph_message_kind_res.txt")
+            .unwrap();
+        assert_eq!(canvas.to_string(), res);
     }
 
-    fn draw_function_calls(
-        &self,
-        canvas: &mut Canvas,
-        callgraph: &CallGraph,
-    ) -> usize {
-        let mut bottom_boundary = 0;
-        for (_component_label, rec) in &self.components {
-            bottom_boundary = max(bottom_boundary, rec.bottom);
-        }
+    #[test]
+    fn test_callgraph_message_kind_ascii() {
+        let txt = "A::run\n  B::sync\n  async B::notify\n";
 
-        for f in &callgraph.func_calls {
-            if !f.0.eq(&f.1 .0) {
-                bottom_boundary =
-                    self.draw_cross_component_call(canvas, f, bottom_boundary);
-            } else {
-                bottom_boundary =
-                    self.draw_same_component_call(canvas, f, bottom_boundary);
-            }
-        }
-        // return the expected bottom of the lifecycle line
-        bottom_boundary + EXTRA_VERTICAL_MARGIN
+        let mut canvas = Canvas::new(500, 500);
+
+        let mut painter = Painter::new(PainterConfig {
+            style: Some(Style::Ascii),
+            ..Default::default()
+        });
+
+        painter.draw(&mut canvas, txt).unwrap();
+
+        canvas.reset_boundary();
+        canvas.print();
+        let lines: Vec<String> = canvas
+            .to_string()
+            .lines()
+            .map(|l| l.trim_end().to_string())
+            .collect();
+        // the filled head of a call and the open one of an async call
+        assert!(lines.contains(&String::from("      |-------->|")));
+        assert!(lines.contains(&String::from("      |--------)|")));
     }
 
-    fn draw_lifecycle_line(
-        &self,
-        canvas: &mut Canvas,
-        components: &HashMap<String, Rectangle>,
-        bottom: usize,
-    ) {
-        for (_, rec) in components {
-            let center = (rec.left + rec.right) / 2;
-            canvas.draw_line_under(&(rec.bottom, center), &(bottom, center));
+    #[test]
+    fn test_callgraph_max_label_width() {
+        let txt = fs::read_to_string("./test/callgraph.txt").unwrap();
+
+        let mut canvas = Canvas::new(500, 500);
+
+        let mut painter = Painter::new(PainterConfig {
+            max_label_width: 12,
+            ..Default::default()
+        });
+
+        painter.draw(&mut canvas, &txt).unwrap();
+
+        canvas.reset_boundary();
+        canvas.print();
+        let res =
+            fs::read_to_string("./test/callgraph_max_label_width_res.txt")
+                .unwrap();
+        assert_eq!(canvas.to_string(), res);
+    }
+
+    #[test]
+    fn test_callgraph_footers() {
+        let txt =
+            fs::read_to_string("./test/callgraph_self_call.txt").unwrap();
+
+        let mut canvas = Canvas::new(500, 500);
+
+        let mut painter = Painter::new(PainterConfig {
+            footers: true,
+            repeat_header_every: Some(3),
+            ..Default::default()
+        });
+
+        painter.draw(&mut canvas, &txt).unwrap();
+
+        canvas.reset_boundary();
+        canvas.print();
+        let res =
+            fs::read_to_string("./test/callgraph_footers_res.txt").unwrap();
+        assert_eq!(canvas.to_string(), res);
+    }
+
+    #[test]
+    fn test_callgraph_metadata() {
+        let txt = fs::read_to_string("./test/callgraph_metadata.txt").unwrap();
+
+        let mut canvas = Canvas::new(500, 500);
+
+        let mut painter = Painter::new(PainterConfig {
+            show_annotations: true,
+            ..Default::default()
+        });
+
+        painter.draw(&mut canvas, &txt).unwrap();
+
+        canvas.reset_boundary();
+        canvas.print();
+        let res =
+            fs::read_to_string("./test/callgraph_metadata_res.txt").unwrap();
+        assert_eq!(canvas.to_string(), res);
+    }
+
+    #[test]
+    fn test_callgraph_directives() {
+        let txt =
+            fs::read_to_string("./test/callgraph_directives.txt").unwrap();
+
+        let mut canvas = Canvas::new(500, 500);
+
+        let mut painter = Painter::new(PainterConfig::default());
+
+        painter.draw(&mut canvas, &txt).unwrap();
+
+        canvas.reset_boundary();
+        canvas.print();
+        let res =
+            fs::read_to_string("./test/callgraph_directives_res.txt").unwrap();
+        assert_eq!(canvas.to_string(), res);
+    }
+
+    #[test]
+    fn test_callgraph_surroundings() {
+        let txt =
+            fs::read_to_string("./test/callgraph_message_kind.txt").unwrap();
+
+        let mut canvas = Canvas::new(500, 500);
+
+        let mut painter = Painter::new(PainterConfig {
+            title: Some(String::from("Kinds")),
+            caption: Some(String::from("Figure 1: message kinds")),
+            footer: Some(String::from("generated by ascii_painter")),
+            legend: true,
+            ..Default::default()
+        });
+
+        painter.draw(&mut canvas, &txt).unwrap();
+
+        canvas.reset_boundary();
+        canvas.print();
+        let res = fs::read_to_string("./test/callgraph_surroundings_res.txt")
+            .unwrap();
+        assert_eq!(canvas.to_string(), res);
+    }
+
+    #[test]
+    fn test_callgraph_markers() {
+        let txt = fs::read_to_string("./test/callgraph_markers.txt").unwrap();
+
+        let mut canvas = Canvas::new(500, 500);
+
+        let mut painter = Painter::new(PainterConfig::default());
+
+        painter.draw(&mut canvas, &txt).unwrap();
+
+        canvas.reset_boundary();
+        canvas.print();
+        let res =
+            fs::read_to_string("./test/callgraph_markers_res.txt").unwrap();
+        assert_eq!(canvas.to_string(), res);
+    }
+
+    #[test]
+    fn test_callgraph_max_width() {
+        let txt = fs::read_to_string("./test/callgraph_wide.txt").unwrap();
+
+        let mut canvas = Canvas::new(500, 500);
+
+        let mut painter = Painter::new(PainterConfig {
+            max_width: Some(80),
+            ..Default::default()
+        });
+
+        painter.draw(&mut canvas, &txt).unwrap();
+
+        canvas.reset_boundary();
+        canvas.print();
+        let res = fs::read_to_string("./test/callgraph_wide_res.txt").unwrap();
+        assert!(res.lines().all(|line| line.chars().count() <= 80));
+        assert_eq!(canvas.to_string(), res);
+    }
+
+    #[test]
+    fn test_callgraph_max_width_surroundings() {
+        let txt = fs::read_to_string("./test/callgraph_chain.txt").unwrap();
+
+        let mut canvas = Canvas::new(500, 500);
+
+        let mut painter = Painter::new(PainterConfig {
+            max_width: Some(40),
+            legend: true,
+            ..Default::default()
+        });
+
+        painter.draw(&mut canvas, &txt).unwrap();
+
+        canvas.reset_boundary();
+        canvas.print();
+        let res =
+            fs::read_to_string("./test/callgraph_chain_res.txt").unwrap();
+        assert!(res.lines().all(|line| line.chars().count() <= 40));
+        assert_eq!(canvas.to_string(), res);
+    }
+
+    #[test]
+    fn test_callgraph_filter() {
+        let txt = fs::read_to_string("./test/callgraph_markers.txt").unwrap();
+
+        let mut canvas = Canvas::new(500, 500);
+
+        let mut painter = Painter::new(PainterConfig {
+            exclude: vec![String::from("Pay*")],
+            collapse_excluded: true,
+            ..Default::default()
+        });
+
+        painter.draw(&mut canvas, &txt).unwrap();
+
+        canvas.reset_boundary();
+        canvas.print();
+        let res =
+            fs::read_to_string("./test/callgraph_filter_res.txt").unwrap();
+        assert_eq!(canvas.to_string(), res);
+    }
+
+    #[test]
+    fn test_callgraph_filter_ascii() {
+        let txt = fs::read_to_string("./test/callgraph_chain.txt").unwrap();
+
+        let mut painter = Painter::new(PainterConfig {
+            exclude: vec![String::from("Ware*")],
+            collapse_excluded: true,
+            style: Some(Style::Ascii),
+            max_width: Some(40),
+            page_gutter: 8,
+            ..Default::default()
+        });
+
+        let pages = painter.draw_pages(&txt).unwrap();
+        let text: Vec<String> =
+            pages.iter().map(|page| page.to_string()).collect();
+        let text = text.join("\u{c}\n");
+        // the collapsed participant and the cut stub names end with `...`
+        assert!(text.is_ascii());
+        assert!(text.contains("|...|"));
+        assert!(text.contains("-> I..."));
+        assert!(text.contains("O...  --"));
+    }
+
+    #[test]
+    fn test_callgraph_groups() {
+        let txt = fs::read_to_string("./test/callgraph_groups.txt").unwrap();
+
+        let mut canvas = Canvas::new(500, 500);
+
+        let mut painter = Painter::new(PainterConfig::default());
+
+        painter.draw(&mut canvas, &txt).unwrap();
+
+        canvas.reset_boundary();
+        canvas.print();
+        let res =
+            fs::read_to_string("./test/callgraph_groups_res.txt").unwrap();
+        assert_eq!(canvas.to_string(), res);
+    }
+
+    #[test]
+    fn test_callgraph_fold_loops() {
+        let txt = fs::read_to_string("./test/callgraph_loop.txt").unwrap();
+
+        let mut canvas = Canvas::new(500, 500);
+
+        let mut painter = Painter::new(PainterConfig {
+            fold_loops: Some(3),
+            ..Default::default()
+        });
+
+        painter.draw(&mut canvas, &txt).unwrap();
+
+        canvas.reset_boundary();
+        canvas.print();
+        let res = fs::read_to_string("./test/callgraph_loop_res.txt").unwrap();
+        assert_eq!(canvas.to_string(), res);
+    }
+
+    #[test]
+    fn test_callgraph_max_height() {
+        let txt = fs::read_to_string("./test/callgraph_markers.txt").unwrap();
+
+        let mut painter = Painter::new(PainterConfig {
+            max_height: Some(16),
+            ..Default::default()
+        });
+
+        let pages = painter.draw_pages(&txt).unwrap();
+        assert_eq!(pages.len(), 4);
+
+        let pages: Vec<String> =
+            pages.iter().map(|page| page.to_string()).collect();
+        let res =
+            fs::read_to_string("./test/callgraph_max_height_res.txt").unwrap();
+        assert!(pages.iter().all(|page| page.lines().count() <= 16));
+        assert_eq!(pages.join("\u{c}\n"), res);
+    }
+
+    #[test]
+    fn test_callgraph_max_height_below_block() {
+        let txt = fs::read_to_string("./test/callgraph_markers.txt").unwrap();
+
+        let mut painter = Painter::new(PainterConfig {
+            max_height: Some(1),
+            ..Default::default()
+        });
+
+        let pages = painter.draw_pages(&txt).unwrap();
+        let pages: Vec<String> =
+            pages.iter().map(|page| page.to_string()).collect();
+        // each page holds a block of messages below the header boxes
+        assert!(pages[0].contains("Checkout"));
+        for page in &pages {
+            assert!(page
+                .lines()
+                .skip(3)
+                .any(|l| l.contains('─') || l.contains('═')));
         }
     }
 
-    pub fn draw(&mut self, canvas: &mut Canvas, callgraph_str: &str) {
-        let callgraph = CallGraph::new(callgraph_str);
-        self.draw_components(canvas, &callgraph);
-        let length = self.draw_function_calls(canvas, &callgraph);
-        self.draw_lifecycle_line(canvas, &self.components, length);
+    #[test]
+    fn test_callgraph_max_height_surroundings() {
+        let txt = fs::read_to_string("./test/callgraph_markers.txt").unwrap();
+
+        let mut painter = Painter::new(PainterConfig {
+            max_height: Some(16),
+            caption: Some(String::from("Figure 3: markers")),
+            legend: true,
+            ..Default::default()
+        });
+
+        let pages = painter.draw_pages(&txt).unwrap();
+        let pages: Vec<String> =
+            pages.iter().map(|page| page.to_string()).collect();
+        // the caption and the legend stay with the messages of the last page
+        assert_eq!(pages.len(), 4);
+        assert!(pages[3].contains("confirm"));
+        assert!(pages[3].contains("Figure 3: markers"));
+        assert!(pages[3].contains("async call"));
+        let res = fs::read_to_string(
+            "./test/callgraph_max_height_surroundings_res.txt",
+        )
+        .unwrap();
+        assert_eq!(pages.join("\u{c}\n"), res);
     }
-}
 
-#[cfg(test)]
-mod test {
-    use super::*;
-    use canvas::Canvas;
-    use std::fs;
+    #[test]
+    fn test_callgraph_tree() {
+        let txt = fs::read_to_string("./test/callgraph_loop.txt").unwrap();
+
+        let mut canvas = Canvas::new(500, 500);
+
+        let mut painter = Painter::new(PainterConfig {
+            diagram: Diagram::Tree,
+            call_counts: true,
+            ..Default::default()
+        });
+
+        painter.draw(&mut canvas, &txt).unwrap();
+
+        canvas.reset_boundary();
+        canvas.print();
+        let res = fs::read_to_string("./test/callgraph_tree_res.txt").unwrap();
+        assert_eq!(canvas.to_string(), res);
+    }
 
     #[test]
-    fn test_callgraph() {
-        let txt = fs::read_to_string("./test/callgraph.txt").unwrap();
+    fn test_callgraph_collaboration() {
+        let txt =
+            fs::read_to_string("./test/callgraph_collaboration.txt").unwrap();
 
         let mut canvas = Canvas::new(500, 500);
 
-        let mut painter = Painter::new();
+        let mut painter = Painter::new(PainterConfig {
+            diagram: Diagram::Collaboration,
+            ..Default::default()
+        });
 
-        painter.draw(&mut canvas, &txt);
+        painter.draw(&mut canvas, &txt).unwrap();
 
         canvas.reset_boundary();
         canvas.print();
-        let res = fs::read_to_string("./test/callgraph_res.txt").unwrap();
+        let res = fs::read_to_string("./test/callgraph_collaboration_res.txt")
+            .unwrap();
         assert_eq!(canvas.to_string(), res);
     }
 
     #[test]
-    fn test_callgraph_self_call() {
+    fn test_callgraph_classes() {
         let txt =
-            fs::read_to_string("./test/callgraph_self_call.txt").unwrap();
+            fs::read_to_string("./test/callgraph_collaboration.txt").unwrap();
 
         let mut canvas = Canvas::new(500, 500);
 
-        let mut painter = Painter::new();
+        let mut painter = Painter::new(PainterConfig {
+            diagram: Diagram::Class,
+            class_grid_width: Some(60),
+            ..Default::default()
+        });
 
-        painter.draw(&mut canvas, &txt);
+        painter.draw(&mut canvas, &txt).unwrap();
 
         canvas.reset_boundary();
         canvas.print();
         let res =
-            fs::read_to_string("./test/callgraph_self_call_res.txt").unwrap();
+            fs::read_to_string("./test/callgraph_classes_res.txt").unwrap();
+        assert_eq!(canvas.to_string(), res);
+    }
+
+    #[test]
+    fn test_flowchart() {
+        let txt = fs::read_to_string("./test/flowchart.txt").unwrap();
+
+        let mut canvas = Canvas::new(500, 500);
+
+        let mut painter = Painter::new(PainterConfig {
+            diagram: Diagram::Flowchart,
+            ..Default::default()
+        });
+
+        painter.draw(&mut canvas, &txt).unwrap();
+
+        canvas.reset_boundary();
+        canvas.print();
+        let res = fs::read_to_string("./test/flowchart_res.txt").unwrap();
         assert_eq!(canvas.to_string(), res);
     }
 
@@ -235,9 +771,9 @@ mod test {
 
             let mut canvas = Canvas::new(500, 500);
 
-            let mut painter = Painter::new();
+            let mut painter = Painter::new(PainterConfig::default());
 
-            painter.draw(&mut canvas, &txt);
+            painter.draw(&mut canvas, &txt).unwrap();
 
             canvas.reset_boundary();
             canvas.print();
@@ -251,9 +787,9 @@ mod test {
 
             let mut canvas = Canvas::new(500, 500);
 
-            let mut painter = Painter::new();
+            let mut painter = Painter::new(PainterConfig::default());
 
-            painter.draw(&mut canvas, &txt);
+            painter.draw(&mut canvas, &txt).unwrap();
 
             canvas.reset_boundary();
             canvas.print();
@@ -0,0 +1,641 @@
+//! This module splits a [`SequenceLayout`] too large for the output into
+//! pages.
+use crate::layout::{
+    wrap, BoxLayout, DelayLayout, DividerLayout, FrameLayout, LegendLayout,
+    MessageLayout, ParticipantLayout, SelfCallLayout, SequenceLayout,
+    TextLayout,
+};
+use canvas::Rectangle;
+use std::cmp::{max, min};
+use std::ops::Range;
+
+/// Moves a layout element by `rows` and `columns`.
+pub trait Translate {
+    fn translate(&mut self, rows: isize, columns: isize);
+}
+
+/// Returns `position` moved by `by`, stopping at 0.
+fn moved(position: usize, by: isize) -> usize {
+    position.saturating_add_signed(by)
+}
+
+impl Translate for TextLayout {
+    fn translate(&mut self, rows: isize, columns: isize) {
+        self.top = moved(self.top, rows);
+        self.left = moved(self.left, columns);
+    }
+}
+
+impl Translate for Rectangle {
+    fn translate(&mut self, rows: isize, columns: isize) {
+        self.top = moved(self.top, rows);
+        self.bottom = moved(self.bottom, rows);
+        self.left = moved(self.left, columns);
+        self.right = moved(self.right, columns);
+    }
+}
+
+impl Translate for BoxLayout {
+    fn translate(&mut self, rows: isize, columns: isize) {
+        self.rec.translate(rows, columns);
+        self.label.translate(rows, columns);
+    }
+}
+
+impl Translate for ParticipantLayout {
+    fn translate(&mut self, rows: isize, columns: isize) {
+        self.center = moved(self.center, columns);
+        for b in &mut self.boxes {
+            b.translate(rows, columns);
+        }
+        for segment in self.lifeline.iter_mut().chain(&mut self.broken) {
+            *segment = (moved(segment.0, rows), moved(segment.1, rows));
+        }
+        self.destroyed = self.destroyed.map(|row| moved(row, rows));
+        for row in &mut self.continued {
+            *row = moved(*row, rows);
+        }
+    }
+}
+
+impl Translate for MessageLayout {
+    fn translate(&mut self, rows: isize, columns: isize) {
+        self.row = moved(self.row, rows);
+        self.from = moved(self.from, columns);
+        self.to = moved(self.to, columns);
+        self.label.translate(rows, columns);
+    }
+}
+
+impl Translate for SelfCallLayout {
+    fn translate(&mut self, rows: isize, columns: isize) {
+        self.column = moved(self.column, columns);
+        self.right = moved(self.right, columns);
+        self.top = moved(self.top, rows);
+        self.bottom = moved(self.bottom, rows);
+        self.label.translate(rows, columns);
+    }
+}
+
+impl Translate for FrameLayout {
+    fn translate(&mut self, rows: isize, columns: isize) {
+        self.rec.translate(rows, columns);
+        self.label.translate(rows, columns);
+    }
+}
+
+impl Translate for DividerLayout {
+    fn translate(&mut self, rows: isize, columns: isize) {
+        self.row = moved(self.row, rows);
+        self.left = moved(self.left, columns);
+        self.right = moved(self.right, columns);
+        self.label.translate(rows, columns);
+    }
+}
+
+impl Translate for DelayLayout {
+    fn translate(&mut self, rows: isize, columns: isize) {
+        self.rows = (moved(self.rows.0, rows), moved(self.rows.1, rows));
+        if let Some(label) = &mut self.label {
+            label.translate(rows, columns);
+        }
+    }
+}
+
+impl Translate for LegendLayout {
+    fn translate(&mut self, rows: isize, columns: isize) {
+        self.rec.translate(rows, columns);
+        for entry in &mut self.entries {
+            entry.message.translate(rows, columns);
+        }
+    }
+}
+
+impl Translate for SequenceLayout {
+    fn translate(&mut self, rows: isize, columns: isize) {
+        for p in &mut self.participants {
+            p.translate(rows, columns);
+        }
+        for m in &mut self.messages {
+            m.translate(rows, columns);
+        }
+        for s in &mut self.self_calls {
+            s.translate(rows, columns);
+        }
+        for f in self.frames.iter_mut().chain(&mut self.groups) {
+            f.translate(rows, columns);
+        }
+        for d in &mut self.dividers {
+            d.translate(rows, columns);
+        }
+        for d in &mut self.delays {
+            d.translate(rows, columns);
+        }
+        let mut texts = [&mut self.title, &mut self.caption, &mut self.footer];
+        let texts = texts.iter_mut().filter_map(|text| text.as_mut());
+        for text in self.stubs.iter_mut().chain(texts) {
+            text.translate(rows, columns);
+        }
+        if let Some(legend) = &mut self.legend {
+            legend.translate(rows, columns);
+        }
+        self.width = moved(self.width, columns);
+        self.height = moved(self.height, rows);
+    }
+}
+
+/// Where a message end is relative to a page.
+#[derive(Clone, Copy, PartialEq)]
+enum Side {
+    Left,
+    On,
+    Right,
+}
+
+impl SequenceLayout {
+    /// Splits the diagram into pages of participants from left to right, each
+    /// at most `max_width` columns wide if its participants allow. Every page
+    /// but the first starts with a `gutter` of columns, where the messages
+    /// from or to the participants on the left are drawn from as stub arrows
+    /// labeled with their names, cut short with `ellipsis` if longer than
+    /// the gutter. Every page but the last ends with a gutter for the
+    /// participants on the right the same way.
+    pub fn split_columns(
+        &self,
+        max_width: usize,
+        gutter: usize,
+        ellipsis: &str,
+    ) -> Vec<SequenceLayout> {
+        let count = self.participants.len();
+        let mut pages = Vec::new();
+        let mut first = 0;
+        let mut start = 0;
+        for i in 0..count {
+            let reserved = if i + 1 < count { gutter } else { 0 };
+            if i > first && self.extent(i) + reserved > start + max_width {
+                let right = start + max_width;
+                pages.push(self.page(
+                    start,
+                    right,
+                    first..i,
+                    gutter,
+                    ellipsis,
+                ));
+                first = i;
+                start = self.participant_left(i).saturating_sub(gutter);
+            }
+        }
+        let right = start + max_width;
+        pages.push(self.page(start, right, first..count, gutter, ellipsis));
+        pages
+    }
+
+    /// Returns the leftmost column of the boxes, self-calls and their labels
+    /// of the `i`-th participant.
+    fn participant_left(&self, i: usize) -> usize {
+        let p = &self.participants[i];
+        let boxes = p.boxes.iter().map(|b| b.rec.left);
+        let self_calls = self
+            .self_calls
+            .iter()
+            .filter(|s| s.column == p.center)
+            .map(|s| s.columns().0);
+        boxes.chain(self_calls).min().unwrap_or(p.center)
+    }
+
+    /// Returns the column right after the boxes, self-calls and their labels
+    /// of the `i`-th participant.
+    fn extent(&self, i: usize) -> usize {
+        let p = &self.participants[i];
+        let boxes = p.boxes.iter().map(|b| b.rec.right + 1);
+        let self_calls = self
+            .self_calls
+            .iter()
+            .filter(|s| s.column == p.center)
+            .map(|s| s.columns().1);
+        boxes.chain(self_calls).max().unwrap_or(p.center + 1)
+    }
+
+    /// Returns the page of columns `left..right` with `participants`.
+    fn page(
+        &self,
+        left: usize,
+        right: usize,
+        participants: Range<usize>,
+        gutter: usize,
+        ellipsis: &str,
+    ) -> SequenceLayout {
+        let side = |p: Option<usize>| match p {
+            Some(p) if participants.contains(&p) => Side::On,
+            Some(p) if p >= participants.end => Side::Right,
+            _ => Side::Left,
+        };
+        let index = |p: Option<usize>| {
+            p.filter(|p| participants.contains(p))
+                .map(|p| p - participants.start)
+        };
+        let centers: Vec<usize> = self.participants[participants.clone()]
+            .iter()
+            .map(|p| p.center)
+            .collect();
+
+        let mut page = SequenceLayout {
+            width: 0,
+            height: 0,
+            participants: self.participants[participants.clone()].to_vec(),
+            messages: Vec::new(),
+            self_calls: self
+                .self_calls
+                .iter()
+                .filter(|s| centers.contains(&s.column))
+                .cloned()
+                .collect(),
+            frames: Vec::new(),
+            groups: Vec::new(),
+            dividers: self.dividers.clone(),
+            delays: self.delays.clone(),
+            stubs: Vec::new(),
+            title: self.title.clone(),
+            caption: self.caption.clone(),
+            footer: self.footer.clone(),
+            legend: self.legend.clone(),
+        };
+
+        for m in &self.messages {
+            let (caller, callee) = m.endpoints;
+            let mut m = m.clone();
+            m.endpoints = (index(caller), index(callee));
+            match (side(caller), side(callee)) {
+                (Side::On, Side::On) => {}
+                (Side::On, off) => {
+                    let name = self.stub_name(callee, gutter, ellipsis);
+                    m.to = page.stub(&m, off, name, left, right);
+                    rewrap(&mut m);
+                }
+                (off, Side::On) => {
+                    let name = self.stub_name(caller, gutter, ellipsis);
+                    m.from = page.stub(&m, off, name, left, right);
+                    rewrap(&mut m);
+                }
+                _ => continue,
+            }
+            page.messages.push(m);
+        }
+
+        for (frames, clipped) in [
+            (&self.frames, &mut page.frames),
+            (&self.groups, &mut page.groups),
+        ] {
+            for frame in frames {
+                if frame.rec.right < left || frame.rec.left >= right {
+                    continue;
+                }
+                let mut frame = frame.clone();
+                frame.rec.left = max(frame.rec.left, left);
+                frame.rec.right = min(frame.rec.right, right - 1);
+                clip(&mut frame.label, frame.rec.left + 1, frame.rec.right);
+                clipped.push(frame);
+            }
+        }
+
+        // the items across the whole diagram are moved to the left edge of
+        // the page, to be centered on its own width
+        let mut texts = [&mut page.title, &mut page.caption, &mut page.footer];
+        let texts = texts.iter_mut().filter_map(|text| text.as_mut());
+        let labels = page.delays.iter_mut().filter_map(|d| d.label.as_mut());
+        for text in texts.chain(labels) {
+            text.left = left;
+        }
+        for divider in &mut page.dividers {
+            divider.left = left;
+            divider.right = left;
+            divider.label.left = left;
+        }
+        if let Some(legend) = &mut page.legend {
+            legend.move_to(left);
+        }
+
+        page.translate(0, -(left as isize));
+        page.resize();
+        page.center();
+        page
+    }
+
+    /// Returns the name of `participant` for a stub arrow, shortened to fit
+    /// in the `gutter` with at least 3 columns of arrow and ending with
+    /// `ellipsis` if cut, or `None` for outside the diagram.
+    fn stub_name(
+        &self,
+        participant: Option<usize>,
+        gutter: usize,
+        ellipsis: &str,
+    ) -> Option<String> {
+        let width = max(gutter.saturating_sub(4), 1);
+        participant.map(|p| {
+            let name = &self.participants[p].label;
+            if name.chars().count() <= width {
+                name.to_owned()
+            } else {
+                let kept = width.saturating_sub(ellipsis.chars().count());
+                let mut name: String = name.chars().take(kept).collect();
+                name.push_str(ellipsis);
+                name
+            }
+        })
+    }
+
+    /// Writes `name` at the `side` edge of the page of columns `left..right`
+    /// on the row of the message `m`, and returns the column the stub arrow
+    /// of `m` ends at there.
+    fn stub(
+        &mut self,
+        m: &MessageLayout,
+        side: Side,
+        name: Option<String>,
+        left: usize,
+        right: usize,
+    ) -> usize {
+        let len = name.as_ref().map_or(0, |name| name.chars().count() + 1);
+        let (column, end) = match side {
+            Side::Left => (left, left + len),
+            // at least 3 columns of arrow from the other end
+            _ => {
+                let end =
+                    max(right.saturating_sub(len + 1), min(m.from, m.to) + 3);
+                (end + 2, end)
+            }
+        };
+        if let Some(name) = name {
+            self.stubs.push(TextLayout {
+                top: m.row,
+                left: column,
+                lines: vec![name],
+            });
+        }
+        end
+    }
+}
+
+/// Wraps the label of the message `m` again to fit its shortened arrow,
+/// keeping its bottom line above the arrow.
+fn rewrap(m: &mut MessageLayout) {
+    let left = min(m.from, m.to);
+    let width = max((max(m.from, m.to) - left).saturating_sub(3), 1);
+    let lines = wrap(&m.label.lines.join(" "), width);
+    m.label = TextLayout {
+        top: m.row.saturating_sub(lines.len()),
+        left: left + 2,
+        lines,
+    };
+}
+
+/// Truncates the lines of `text` to the columns before `right`, and moves
+/// them right of `left`.
+fn clip(text: &mut TextLayout, left: usize, right: usize) {
+    text.left = max(text.left, left);
+    let width = max(right.saturating_sub(text.left), 1);
+    for line in &mut text.lines {
+        *line = line.chars().take(width).collect();
+    }
+}
+
+impl SequenceLayout {
+    /// Splits the diagram into pages of at most `max_height` rows if its
+    /// messages allow, breaking only between them. Every page repeats the
+    /// title and the header boxes of the participants alive at its top, and
+    /// marks the lifelines continuing across a break with `⋮`. Every page
+    /// holds at least one block of messages, even if taller than the limit.
+    /// The caption, footer and legend stay below the messages of the last
+    /// page, even if it gets longer.
+    pub fn split_rows(&self, max_height: usize) -> Vec<SequenceLayout> {
+        let header_bottom = self.header_bottom();
+        // rows left to the messages besides the markers above and below
+        let capacity = max(max_height.saturating_sub(header_bottom + 2), 1);
+        let mut cuts = vec![header_bottom];
+        for (i, (top, bottom)) in
+            self.blocks(header_bottom).into_iter().enumerate()
+        {
+            let start = cuts[cuts.len() - 1];
+            if i > 0 && bottom - start > capacity {
+                cuts.push(top);
+            }
+        }
+        cuts.push(max(self.height, header_bottom));
+
+        cuts.windows(2)
+            .map(|cut| self.rows_page(header_bottom, cut[0]..cut[1]))
+            .collect()
+    }
+
+    /// Returns the top row of the header boxes on top of the diagram.
+    fn header_top(&self) -> usize {
+        let headers = self.participants.iter().filter_map(|p| p.boxes.first());
+        headers.map(|b| b.rec.top).min().unwrap_or(0)
+    }
+
+    /// Returns the row right after the header boxes on top of the diagram.
+    fn header_bottom(&self) -> usize {
+        let top = self.header_top();
+        let headers = self.participants.iter().filter_map(|p| p.boxes.first());
+        headers
+            .filter(|b| b.rec.top == top)
+            .map(|b| b.rec.bottom + 1)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Returns the spans of rows from `header_bottom` that a page break
+    /// cannot cross, merged and from top to bottom.
+    fn blocks(&self, header_bottom: usize) -> Vec<(usize, usize)> {
+        let mut spans = Vec::new();
+        for m in &self.messages {
+            spans.push((min(m.label.top, m.row), m.row + 1));
+        }
+        for s in &self.self_calls {
+            spans.push((s.top, max(s.bottom + 1, s.label.bottom())));
+        }
+        for p in &self.participants {
+            for b in &p.boxes {
+                spans.push((b.rec.top, b.rec.bottom + 1));
+            }
+            if let Some(destroyed) = p.destroyed {
+                spans.push((destroyed, destroyed + 1));
+            }
+        }
+        for f in &self.frames {
+            spans.push((f.rec.top, max(f.rec.top + 1, f.label.bottom())));
+            spans.push((f.rec.bottom, f.rec.bottom + 1));
+        }
+        for g in &self.groups {
+            spans.push((g.rec.bottom, g.rec.bottom + 1));
+        }
+        for d in &self.dividers {
+            spans.push((d.row, d.row + 1));
+        }
+        for d in &self.delays {
+            spans.push(d.rows);
+        }
+        spans.retain(|&(top, _)| top >= header_bottom);
+        spans.sort_unstable();
+
+        let mut blocks: Vec<(usize, usize)> = Vec::new();
+        for (top, bottom) in spans {
+            match blocks.last_mut() {
+                Some(last) if top < last.1 => last.1 = max(last.1, bottom),
+                _ => blocks.push((top, bottom)),
+            }
+        }
+        blocks
+    }
+
+    /// Returns the page of `rows` below the header rows ending at
+    /// `header_bottom`. On every page but the first, the rows are moved up
+    /// right below the header and a row of markers.
+    fn rows_page(
+        &self,
+        header_bottom: usize,
+        rows: Range<usize>,
+    ) -> SequenceLayout {
+        let first = rows.start == header_bottom;
+        let last = rows.end >= self.height;
+        let delta = if first {
+            0
+        } else {
+            (header_bottom + 1) as isize - rows.start as isize
+        };
+        let within = |row: &usize| rows.contains(row);
+        let translated = |text: &Option<TextLayout>| {
+            text.as_ref().filter(|t| within(&t.top)).map(|t| {
+                let mut t = t.clone();
+                t.translate(delta, 0);
+                t
+            })
+        };
+
+        let mut page = SequenceLayout {
+            width: 0,
+            height: 0,
+            participants: Vec::new(),
+            messages: kept(&self.messages, &rows, delta, |m| m.row),
+            self_calls: kept(&self.self_calls, &rows, delta, |s| s.top),
+            frames: Vec::new(),
+            groups: Vec::new(),
+            dividers: kept(&self.dividers, &rows, delta, |d| d.row),
+            delays: kept(&self.delays, &rows, delta, |d| d.rows.0),
+            stubs: kept(&self.stubs, &rows, delta, |t| t.top),
+            title: self.title.clone(),
+            caption: translated(&self.caption),
+            footer: translated(&self.footer),
+            legend: None,
+        };
+        if let Some(legend) = &self.legend {
+            if within(&legend.rec.top) {
+                let mut legend = legend.clone();
+                legend.translate(delta, 0);
+                page.legend = Some(legend);
+            }
+        }
+
+        let header_top = self.header_top();
+        for p in &self.participants {
+            let mut q = ParticipantLayout {
+                boxes: Vec::new(),
+                lifeline: Vec::new(),
+                destroyed: None,
+                broken: Vec::new(),
+                continued: Vec::new(),
+                ..p.clone()
+            };
+            let crossing =
+                |row| p.lifeline.iter().any(|&(s, e)| s < row && row < e);
+            // the header on top of the page
+            match p.boxes.first() {
+                Some(header) if first && header.rec.top < header_bottom => {
+                    q.boxes.push(header.clone());
+                }
+                Some(header) if !first && crossing(rows.start) => {
+                    let mut header = header.clone();
+                    let by = header_top as isize - header.rec.top as isize;
+                    header.translate(by, 0);
+                    q.lifeline.push((header.rec.bottom, header_bottom + 1));
+                    q.continued.push(header_bottom);
+                    q.boxes.push(header);
+                }
+                _ => {}
+            }
+            for b in &p.boxes {
+                if b.rec.top >= header_bottom && within(&b.rec.top) {
+                    let mut b = b.clone();
+                    b.translate(delta, 0);
+                    q.boxes.push(b);
+                }
+            }
+            let top = if first { 0 } else { rows.start - 1 };
+            for &(s, e) in &p.lifeline {
+                let (s, e) = (max(s, top), min(e, rows.end));
+                if s + 1 < e {
+                    q.lifeline.push((moved(s, delta), moved(e, delta)));
+                }
+            }
+            for &(s, e) in &p.broken {
+                let (s, e) = (max(s, rows.start), min(e, rows.end));
+                if s < e {
+                    q.broken.push((moved(s, delta), moved(e, delta)));
+                }
+            }
+            q.destroyed = p
+                .destroyed
+                .filter(|row| within(row))
+                .map(|row| moved(row, delta));
+            if !last && crossing(rows.end) {
+                q.continued.push(moved(rows.end, delta));
+            }
+            page.participants.push(q);
+        }
+
+        for frame in &self.frames {
+            if frame.rec.bottom < rows.start || frame.rec.top >= rows.end {
+                continue;
+            }
+            let mut frame = frame.clone();
+            if !within(&frame.rec.top) {
+                frame.label.lines.clear();
+            }
+            frame.rec.top = max(frame.rec.top, rows.start);
+            frame.rec.bottom = min(frame.rec.bottom, rows.end - 1);
+            frame.translate(delta, 0);
+            page.frames.push(frame);
+        }
+        // group boxes start above the header of every page
+        for group in &self.groups {
+            if group.rec.bottom < rows.start {
+                continue;
+            }
+            let mut group = group.clone();
+            group.rec.bottom = moved(min(group.rec.bottom, rows.end), delta);
+            page.groups.push(group);
+        }
+
+        page.resize();
+        page
+    }
+}
+
+/// Returns the `elements` whose `top` row is within `rows`, moved by `delta`
+/// rows.
+fn kept<T: Clone + Translate>(
+    elements: &[T],
+    rows: &Range<usize>,
+    delta: isize,
+    top: impl Fn(&T) -> usize,
+) -> Vec<T> {
+    elements
+        .iter()
+        .filter(|e| rows.contains(&top(e)))
+        .map(|e| {
+            let mut e = e.clone();
+            e.translate(delta, 0);
+            e
+        })
+        .collect()
+}
@@ -0,0 +1,355 @@
+//! This module includes the [`Renderer`] trait, which draws a
+//! [`SequenceLayout`], the [`CollaborationRenderer`], [`ClassRenderer`] and
+//! [`FlowchartRenderer`] traits of the other diagrams, and
+//! [`CanvasRenderer`], their [`Canvas`] text backend.
+use crate::callgraph::MessageKind;
+use crate::classes::ClassDiagramLayout;
+use crate::collaboration::CollaborationLayout;
+use crate::flowchart::{FlowchartLayout, Shape};
+use crate::layout::{ArrowHead, SequenceLayout, TextLayout};
+use canvas::Canvas;
+
+/// A backend drawing [`SequenceLayout`].
+pub trait Renderer {
+    /// Draws `layout`.
+    fn render(&mut self, layout: &SequenceLayout);
+}
+
+/// A backend drawing [`CollaborationLayout`].
+pub trait CollaborationRenderer {
+    /// Draws the collaboration diagram `layout`.
+    fn render_collaboration(&mut self, layout: &CollaborationLayout);
+}
+
+/// A backend drawing [`ClassDiagramLayout`].
+pub trait ClassRenderer {
+    /// Draws the class diagram `layout`.
+    fn render_classes(&mut self, layout: &ClassDiagramLayout);
+}
+
+/// A backend drawing [`FlowchartLayout`].
+pub trait FlowchartRenderer {
+    /// Draws the flowchart `layout`.
+    fn render_flowchart(&mut self, layout: &FlowchartLayout);
+}
+
+/// Draws [`SequenceLayout`], [`CollaborationLayout`],
+/// [`ClassDiagramLayout`] and [`FlowchartLayout`] as text on a [`Canvas`].
+pub struct CanvasRenderer<'a> {
+    canvas: &'a mut Canvas,
+}
+
+impl<'a> CanvasRenderer<'a> {
+    pub fn new(canvas: &'a mut Canvas) -> Self {
+        CanvasRenderer { canvas }
+    }
+
+    fn draw_text(&mut self, text: &TextLayout) {
+        for (i, line) in text.lines.iter().enumerate() {
+            self.canvas.draw_text(&(text.top + i, text.left), line);
+        }
+    }
+
+    /// Draws an orthogonal line through `points`, from a junction on the
+    /// border of the box it leaves.
+    fn draw_path(&mut self, points: &[(usize, usize)]) {
+        for pair in points.windows(2) {
+            self.canvas.draw_line_under(&pair[0], &pair[1]);
+        }
+        for bend in points.windows(3) {
+            self.canvas
+                .draw_point(&bend[1], corner(bend[0], bend[1], bend[2]));
+        }
+        if let [first, second, ..] = points {
+            let junction = if second.0 > first.0 {
+                '┬'
+            } else if second.0 < first.0 {
+                '┴'
+            } else if second.1 > first.1 {
+                '├'
+            } else {
+                '┤'
+            };
+            self.canvas.draw_point(first, junction);
+        }
+    }
+
+    /// Draws an orthogonal arrow through `points`, from a junction on the
+    /// border of the box it leaves to an arrowhead before the border of the
+    /// box it enters.
+    fn draw_edge(&mut self, points: &[(usize, usize)]) {
+        self.draw_path(points);
+        if let [.., before, last] = *points {
+            let (head, at) = if before.0 < last.0 {
+                ('▼', (last.0 - 1, last.1))
+            } else if before.0 > last.0 {
+                ('▲', (last.0 + 1, last.1))
+            } else if before.1 < last.1 {
+                ('►', (last.0, last.1 - 1))
+            } else {
+                ('◄', (last.0, last.1 + 1))
+            };
+            self.canvas.draw_point(&at, head);
+        }
+    }
+
+    fn draw_arrowed_line(
+        &mut self,
+        start: &(usize, usize),
+        end: &(usize, usize),
+        head: ArrowHead,
+    ) {
+        match head {
+            ArrowHead::Filled => self.canvas.draw_arrowed_line(start, end),
+            ArrowHead::Open => self.canvas.draw_open_arrowed_line(start, end),
+        };
+    }
+}
+
+impl Renderer for CanvasRenderer<'_> {
+    fn render(&mut self, layout: &SequenceLayout) {
+        // group boxes are drawn behind everything
+        for group in &layout.groups {
+            self.canvas.draw_rectangle(&group.rec);
+        }
+
+        let texts = [&layout.title, &layout.caption, &layout.footer];
+        for text in texts.iter().filter_map(|text| text.as_ref()) {
+            self.draw_text(text);
+        }
+        if let Some(legend) = &layout.legend {
+            self.canvas.draw_rectangle(&legend.rec);
+            for entry in &legend.entries {
+                let e = &entry.message;
+                self.draw_arrowed_line(
+                    &(e.row, e.from),
+                    &(e.row, e.to),
+                    e.head,
+                );
+                // the arrow ends at the box it creates, or the `X` of the
+                // lifeline it destroys
+                match entry.kind {
+                    MessageKind::Create => {
+                        self.canvas.draw_point(&(e.row, e.to + 1), '□')
+                    }
+                    MessageKind::Destroy => {
+                        self.canvas.draw_point(&(e.row, e.to + 1), 'X')
+                    }
+                    MessageKind::Sync | MessageKind::Async => {}
+                }
+                self.draw_text(&e.label);
+            }
+        }
+
+        for frame in &layout.frames {
+            self.canvas.draw_rectangle(&frame.rec);
+        }
+
+        for participant in &layout.participants {
+            for b in &participant.boxes {
+                self.canvas.draw_rectangle(&b.rec);
+                self.draw_text(&b.label);
+            }
+        }
+
+        for m in &layout.messages {
+            self.draw_arrowed_line(&(m.row, m.from), &(m.row, m.to), m.head);
+            self.draw_text(&m.label);
+        }
+        for stub in &layout.stubs {
+            self.draw_text(stub);
+        }
+
+        for s in &layout.self_calls {
+            let (top_corner, bottom_corner, head) = if s.right > s.column {
+                ('┐', '┘', s.column + 1)
+            } else {
+                ('┌', '└', s.column - 1)
+            };
+            self.canvas.draw_line(&(s.top, s.column), &(s.top, s.right));
+            self.canvas.draw_point(&(s.top, s.right), top_corner);
+            self.canvas
+                .draw_line(&(s.top, s.right), &(s.bottom, s.right));
+            self.draw_text(&s.label);
+            self.draw_arrowed_line(
+                &(s.bottom, s.right),
+                &(s.bottom, head),
+                s.head,
+            );
+            self.canvas.draw_point(&(s.bottom, s.right), bottom_corner);
+        }
+
+        // lifelines are drawn under everything drawn so far
+        for participant in &layout.participants {
+            let center = participant.center;
+            if let Some(destroyed) = participant.destroyed {
+                self.canvas.draw_point(&(destroyed, center), 'X');
+            }
+            for &row in &participant.continued {
+                self.canvas.draw_point(&(row, center), '⋮');
+            }
+            for &(start, end) in &participant.broken {
+                for row in start..end {
+                    self.canvas.draw_point(&(row, center), '┊');
+                }
+            }
+            for &(start, end) in &participant.lifeline {
+                self.canvas
+                    .draw_line_under(&(start, center), &(end, center));
+            }
+        }
+
+        // frame and group labels, dividers and delay labels are drawn over
+        // the lifelines
+        for frame in layout.frames.iter().chain(&layout.groups) {
+            self.draw_text(&frame.label);
+        }
+        for divider in &layout.dividers {
+            let band = "═".repeat(divider.right - divider.left);
+            self.canvas.draw_text(&(divider.row, divider.left), &band);
+            self.draw_text(&divider.label);
+        }
+        let labels = layout.delays.iter().filter_map(|d| d.label.as_ref());
+        for label in labels {
+            self.draw_text(label);
+        }
+    }
+}
+
+impl CollaborationRenderer for CanvasRenderer<'_> {
+    fn render_collaboration(&mut self, layout: &CollaborationLayout) {
+        for b in &layout.boxes {
+            self.canvas.draw_rectangle(&b.rec);
+            self.draw_text(&b.label);
+        }
+
+        for edge in &layout.edges {
+            self.draw_edge(&edge.points);
+        }
+
+        // labels are drawn over the arrows passing through them
+        for label in &layout.labels {
+            self.draw_text(label);
+        }
+    }
+}
+
+impl ClassRenderer for CanvasRenderer<'_> {
+    fn render_classes(&mut self, layout: &ClassDiagramLayout) {
+        for class in &layout.classes {
+            let rec = &class.rec;
+            self.canvas.draw_rectangle(rec);
+            self.canvas.draw_line(
+                &(class.divider, rec.left),
+                &(class.divider, rec.right),
+            );
+            self.canvas.draw_point(&(class.divider, rec.left), '├');
+            self.canvas.draw_point(&(class.divider, rec.right), '┤');
+            self.draw_text(&class.name);
+            self.draw_text(&class.functions);
+        }
+
+        for edge in &layout.edges {
+            self.draw_edge(&edge.points);
+        }
+        // arrows crossing each other are drawn through one another
+        let paths: Vec<&[(usize, usize)]> =
+            layout.edges.iter().map(|e| e.points.as_slice()).collect();
+        for at in crossings(&paths) {
+            self.canvas.draw_point(&at, '┼');
+        }
+    }
+}
+
+impl FlowchartRenderer for CanvasRenderer<'_> {
+    fn render_flowchart(&mut self, layout: &FlowchartLayout) {
+        for node in &layout.nodes {
+            let rec = &node.rec;
+            self.canvas.draw_rectangle(rec);
+            if node.shape == Shape::Terminal {
+                self.canvas.draw_point(&(rec.top, rec.left), '╭');
+                self.canvas.draw_point(&(rec.top, rec.right), '╮');
+                self.canvas.draw_point(&(rec.bottom, rec.right), '╯');
+                self.canvas.draw_point(&(rec.bottom, rec.left), '╰');
+            }
+            self.draw_text(&node.label);
+        }
+
+        for edge in &layout.edges {
+            self.draw_edge(&edge.points);
+        }
+        // a merging line joins the arrow it ends on from the side
+        for merge in &layout.merges {
+            self.draw_path(&merge.points);
+            if let [.., before, last] = *merge.points {
+                let junction = if before.1 > last.1 { '├' } else { '┤' };
+                self.canvas.draw_point(&last, junction);
+            }
+        }
+
+        // the sides of the decisions are drawn over the arrows leaving them
+        for node in &layout.nodes {
+            if node.shape == Shape::Decision {
+                let rec = &node.rec;
+                let middle = (rec.top + rec.bottom) / 2;
+                self.canvas.draw_point(&(middle, rec.left), '◇');
+                self.canvas.draw_point(&(middle, rec.right), '◇');
+            }
+        }
+        for label in &layout.labels {
+            self.draw_text(label);
+        }
+    }
+}
+
+/// Returns the points where a horizontal segment of one of the orthogonal
+/// `paths` crosses a vertical segment of another, both away from their ends.
+fn crossings(paths: &[&[(usize, usize)]]) -> Vec<(usize, usize)> {
+    let between = |x: usize, a: usize, b: usize| a.min(b) < x && x < a.max(b);
+    let mut points = Vec::new();
+    for (i, a) in paths.iter().enumerate() {
+        for (j, b) in paths.iter().enumerate() {
+            if i == j {
+                continue;
+            }
+            for h in a.windows(2).filter(|h| h[0].0 == h[1].0) {
+                for v in b.windows(2).filter(|v| v[0].1 == v[1].1) {
+                    let at = (h[0].0, v[0].1);
+                    if between(at.1, h[0].1, h[1].1)
+                        && between(at.0, v[0].0, v[1].0)
+                    {
+                        points.push(at);
+                    }
+                }
+            }
+        }
+    }
+    points
+}
+
+/// Returns the character of the bend `at` of an orthogonal line going from
+/// `from` to `to` through it.
+fn corner(
+    from: (usize, usize),
+    at: (usize, usize),
+    to: (usize, usize),
+) -> char {
+    let vertical = |p: (usize, usize)| p.1 == at.1;
+    match (vertical(from), vertical(to)) {
+        (true, true) => '│',
+        (false, false) => '─',
+        _ => {
+            let (v, h) = if vertical(from) {
+                (from, to)
+            } else {
+                (to, from)
+            };
+            match (v.0 < at.0, h.1 < at.1) {
+                (true, true) => '┘',
+                (true, false) => '└',
+                (false, true) => '┐',
+                (false, false) => '┌',
+            }
+        }
+    }
+}
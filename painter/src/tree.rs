@@ -0,0 +1,223 @@
+//! This module includes the [`CallTree`] type, which holds the calls of a
+//! [`CallGraph`] as a tree grouped by component, drawn like the output of
+//! the `tree` command.
+use crate::callgraph::CallGraph;
+use crate::filter::is_nested;
+
+/// A component, or a function with the calls made from it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TreeNode {
+    pub label: String,
+    /// Number of calls merged into the node, `None` for a component.
+    pub count: Option<usize>,
+    pub children: Vec<TreeNode>,
+}
+
+impl TreeNode {
+    /// Returns the text of the node, followed by its call count if
+    /// `counts`.
+    fn text(&self, counts: bool) -> String {
+        match self.count {
+            Some(count) if counts => format!("{} ×{}", self.label, count),
+            _ => self.label.clone(),
+        }
+    }
+}
+
+/// The calls of a [`CallGraph`] as a tree. Its roots are the components
+/// called from outside or making the calls of a section. Below a component
+/// are its functions called, and below a function are the functions of the
+/// same component it calls, and the other components it calls with their
+/// functions. The calls of a function from the same place are merged into
+/// one node, and the nodes are in the order of their first call.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallTree {
+    pub roots: Vec<TreeNode>,
+}
+
+impl CallTree {
+    pub fn new(callgraph: &CallGraph) -> Self {
+        let calls = &callgraph.func_calls;
+        // the calls nested right in each call, and the calls nested in none
+        let mut children = vec![Vec::new(); calls.len()];
+        let mut roots = Vec::new();
+        let mut ancestors: Vec<usize> = Vec::new();
+        for (i, f) in calls.iter().enumerate() {
+            while ancestors.last().is_some_and(|&a| !is_nested(f, &calls[a])) {
+                ancestors.pop();
+            }
+            match ancestors.last() {
+                Some(&a) => children[a].push(i),
+                None => roots.push(i),
+            }
+            ancestors.push(i);
+        }
+
+        // the calls of a section are made from the component of the section
+        let mut components: Vec<(&str, Vec<usize>)> = Vec::new();
+        for i in roots {
+            let f = &calls[i];
+            let component = if f.caller.is_empty() {
+                f.callee.0.as_str()
+            } else {
+                f.caller.as_str()
+            };
+            match components.iter_mut().find(|(c, _)| *c == component) {
+                Some((_, calls)) => calls.push(i),
+                None => components.push((component, vec![i])),
+            }
+        }
+        let builder = TreeBuilder {
+            callgraph,
+            children,
+        };
+        let roots = components
+            .iter()
+            .map(|(component, calls)| builder.component(component, calls))
+            .collect();
+        CallTree { roots }
+    }
+
+    /// Returns the lines of the tree drawn with `├──`, `└──` and `│`
+    /// connectors, with the call counts of the functions if `counts`.
+    pub fn lines(&self, counts: bool) -> Vec<String> {
+        let mut lines = Vec::new();
+        for root in &self.roots {
+            lines.push(root.text(counts));
+            push_children(root, "", counts, &mut lines);
+        }
+        lines
+    }
+}
+
+/// Pushes the lines of the descendants of `node` to `lines`, each behind
+/// `prefix`, the connectors of the levels above.
+fn push_children(
+    node: &TreeNode,
+    prefix: &str,
+    counts: bool,
+    lines: &mut Vec<String>,
+) {
+    for (i, child) in node.children.iter().enumerate() {
+        let (branch, indent) = if i + 1 == node.children.len() {
+            ("└── ", "    ")
+        } else {
+            ("├── ", "│   ")
+        };
+        lines.push(format!("{}{}{}", prefix, branch, child.text(counts)));
+        push_children(child, &format!("{}{}", prefix, indent), counts, lines);
+    }
+}
+
+/// What a call is grouped by below the component it is made from.
+#[derive(PartialEq)]
+enum Callee<'a> {
+    /// A function of the same component.
+    Function(&'a str),
+    /// Another component.
+    Component(&'a str),
+}
+
+struct TreeBuilder<'a> {
+    callgraph: &'a CallGraph,
+    /// Indices in `func_calls` of the calls nested right in each call.
+    children: Vec<Vec<usize>>,
+}
+
+impl<'a> TreeBuilder<'a> {
+    /// Returns the node of `component` with the `calls` made from or to it.
+    fn component(&self, component: &str, calls: &[usize]) -> TreeNode {
+        TreeNode {
+            label: self.callgraph.label(component).to_string(),
+            count: None,
+            children: self.nodes(component, calls),
+        }
+    }
+
+    /// Returns the node of the `function` of `component`, merging its
+    /// `calls`.
+    fn function(
+        &self,
+        component: &str,
+        function: &str,
+        calls: &[usize],
+    ) -> TreeNode {
+        let nested: Vec<usize> = calls
+            .iter()
+            .flat_map(|&i| self.children[i].iter().copied())
+            .collect();
+        TreeNode {
+            label: function.to_string(),
+            count: Some(calls.len()),
+            children: self.nodes(component, &nested),
+        }
+    }
+
+    /// Returns the nodes of `calls` made from or to `component`, grouped by
+    /// the function of `component` or the other component called.
+    fn nodes(&self, component: &str, calls: &[usize]) -> Vec<TreeNode> {
+        let mut callees: Vec<(Callee<'a>, Vec<usize>)> = Vec::new();
+        for &i in calls {
+            let (called, function) = &self.callgraph.func_calls[i].callee;
+            let callee = if called == component {
+                Callee::Function(function)
+            } else {
+                Callee::Component(called)
+            };
+            match callees.iter_mut().find(|(c, _)| *c == callee) {
+                Some((_, calls)) => calls.push(i),
+                None => callees.push((callee, vec![i])),
+            }
+        }
+        callees
+            .iter()
+            .map(|(callee, calls)| match callee {
+                Callee::Function(function) => {
+                    self.function(component, function, calls)
+                }
+                Callee::Component(called) => self.component(called, calls),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_call_tree() {
+        let txt = "\
+ClassA::func_1
+  ClassB::func_2
+    ClassB::func_3
+    ClassC::func_5
+    ClassB::func_4
+  ClassB::func_2
+    ClassB::func_3
+
+ClassC
+  ClassB::func_3
+";
+        let callgraph = CallGraph::new(txt).unwrap();
+        let tree = CallTree::new(&callgraph);
+
+        assert_eq!(
+            tree.lines(true),
+            [
+                "ClassA",
+                "└── func_1 ×1",
+                "    └── ClassB",
+                "        └── func_2 ×2",
+                "            ├── func_3 ×2",
+                "            ├── ClassC",
+                "            │   └── func_5 ×1",
+                "            └── func_4 ×1",
+                "ClassC",
+                "└── ClassB",
+                "    └── func_3 ×1",
+            ]
+        );
+        assert_eq!(tree.lines(false)[3], "        └── func_2");
+    }
+}